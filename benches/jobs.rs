@@ -0,0 +1,103 @@
+//! Benchmark of conversion with a growing number of jobs, on a large synthetic Extras bundle.
+//!
+//! Run with `cargo bench --bench jobs`. The number of machines of each source dat can be
+//! set with the `MACHINES` environment variable, and the maximum number of jobs with `JOBS`
+//! (defaults to the number of available CPUs).
+
+use std::env;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::Write;
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::thread;
+use std::time::Instant;
+
+use convert_mame_extras_romvault::dat::generate_content;
+use convert_mame_extras_romvault::profile::Profile;
+use convert_mame_extras_romvault::Config;
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+/// Default number of machines of each source dat.
+const MACHINES: usize = 100_000;
+
+/// Build a source dat with specified number of machines.
+fn dat(name: &str, machines: usize) -> String {
+    let mut dat = format!(
+        "<?xml version=\"1.0\"?>\n<datafile>\n\t<header>\n\t\t<name>{name}</name>\n\t\t<version>0.264</version>\n\t</header>\n"
+    );
+    for machine in 0..machines {
+        let _ = writeln!(
+            dat,
+            "\t<machine name=\"m{machine:06}\">\n\t\t<description>Machine {machine} &amp; co</description>"
+        );
+        for rom in 0..4 {
+            let _ = writeln!(
+                dat,
+                "\t\t<rom name=\"m{machine:06}/{rom}.png\" size=\"{}\" crc=\"{:08x}\" sha1=\"{:040x}\"/>",
+                machine * 10 + rom,
+                machine * 4 + rom,
+                machine * 4 + rom
+            );
+        }
+        dat.push_str("\t</machine>\n");
+    }
+    dat.push_str("</datafile>\n");
+    dat
+}
+
+/// Write a synthetic Extras bundle with specified number of machines in each source dat.
+fn write_bundle(path: &Path, machines: usize) -> zip::result::ZipResult<()> {
+    let mut zip = ZipWriter::new(File::create(path)?);
+    let options = SimpleFileOptions::default();
+    for entry in ["all_non-zipped_content.dat", "artwork.dat", "samples.dat"] {
+        zip.start_file(entry, options)?;
+        zip.write_all(dat(entry, machines).as_bytes())?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+fn main() {
+    let machines = env::var("MACHINES")
+        .ok()
+        .and_then(|machines| machines.parse().ok())
+        .unwrap_or(MACHINES);
+    let dir = env::temp_dir().join("convert-mame-extras-romvault-bench");
+    std::fs::create_dir_all(&dir).expect("cannot create benchmark dir");
+    let input = dir.join("MAME 0.264 EXTRAs.zip");
+    write_bundle(&input, machines).expect("cannot write benchmark bundle");
+    println!("Bundle with {machines} machines per source dat");
+
+    let max_jobs = env::var("JOBS")
+        .ok()
+        .and_then(|jobs| jobs.parse().ok())
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, NonZeroUsize::get));
+    let mut jobs = 1;
+    let mut baseline: Option<(f64, Vec<u8>)> = None;
+    loop {
+        let config = Config::new(&input, None, Profile::default())
+            .with_jobs(NonZeroUsize::new(jobs).unwrap_or(NonZeroUsize::MIN));
+        let now = Instant::now();
+        let (content, _) = generate_content(&config).expect("conversion failed");
+        let elapsed = now.elapsed();
+        let (baseline_secs, baseline_content) =
+            baseline.get_or_insert_with(|| (elapsed.as_secs_f64(), content.clone()));
+        assert!(
+            *baseline_content == content,
+            "output differs with {jobs} jobs"
+        );
+        println!(
+            "{jobs:>3} job{} {elapsed:>10.2?}  x{:.2}",
+            if jobs > 1 { "s" } else { " " },
+            *baseline_secs / elapsed.as_secs_f64()
+        );
+        if jobs >= max_jobs {
+            break;
+        }
+        jobs = (jobs * 2).min(max_jobs);
+    }
+
+    std::fs::remove_dir_all(&dir).expect("cannot remove benchmark dir");
+}
@@ -0,0 +1,308 @@
+//! Conversion of every bundle found in a directory.
+//!
+//! Input files are selected with the naming pattern of the profile, and converted
+//! in parallel. Each output is named from the profile name and the version
+//! extracted from the input file name (e.g. `Extras 0.264.dat`). Inputs mapped to the same
+//! output (e.g. copies of a bundle found in several subdirectories) are rejected before any
+//! conversion.
+
+use anyhow::anyhow;
+use log::debug;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::{fs, iter};
+
+use crate::dat::{generate_output, Summary};
+use crate::pool::map_parallel;
+use crate::profile::Profile;
+use crate::Config;
+
+/// Custom result with any context error.
+type Result<T> = anyhow::Result<T>;
+
+/// Input file found in a directory.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Input {
+    /// Path of input file
+    pub path: PathBuf,
+    /// Name of input file
+    pub file_name: String,
+}
+
+/// Find files matching naming pattern of specified profile in specified directory, sorted by path.
+/// Symbolic links to directories are not followed, so that a link loop cannot be scanned forever.
+///
+/// # Errors
+///
+/// Will return `Err` if directory, or one of its subdirectories when scanned recursively, cannot be read.
+pub fn find_inputs(dir: &Path, recursive: bool, profile: &Profile) -> Result<Vec<Input>> {
+    let mut inputs = Vec::new();
+    let entries =
+        fs::read_dir(dir).map_err(|err| anyhow!("cannot read {}: {err}", dir.display()))?;
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            if recursive {
+                inputs.extend(find_inputs(&path, recursive, profile)?);
+            }
+            continue;
+        }
+        if path.is_dir() {
+            debug!("{} skipped, symbolic link to a directory", path.display());
+            continue;
+        }
+        let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        if profile.extract_version(file_name).is_some() {
+            let file_name = String::from(file_name);
+            inputs.push(Input { path, file_name });
+        }
+    }
+    inputs.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(inputs)
+}
+
+/// Output file name for specified profile and version.
+///
+/// # Examples
+///
+/// ```
+/// use convert_mame_extras_romvault::batch::output_file_name;
+/// use convert_mame_extras_romvault::profile::Profile;
+///
+/// assert_eq!("Extras 0.264.dat", output_file_name(&Profile::default(), Some(0.264)));
+/// assert_eq!("Extras.dat", output_file_name(&Profile::default(), None));
+/// ```
+#[must_use]
+pub fn output_file_name(profile: &Profile, version: Option<f32>) -> String {
+    version.map_or_else(
+        || format!("{}.dat", profile.header.name),
+        |version| format!("{} {version}.dat", profile.header.name),
+    )
+}
+
+/// Check that no two configurations write the same output file.
+///
+/// # Errors
+///
+/// Will return `Err` listing every output file shared by several input files.
+pub fn check_collisions(configs: &[Config]) -> Result<()> {
+    let mut outputs: BTreeMap<&Path, Vec<&Path>> = BTreeMap::new();
+    for config in configs {
+        outputs
+            .entry(&config.output_file_path)
+            .or_default()
+            .push(&config.input_file_path);
+    }
+    let collisions: Vec<String> = outputs
+        .into_iter()
+        .filter(|(_, inputs)| inputs.len() > 1)
+        .map(|(output, inputs)| {
+            format!(
+                "{} would be written from {}",
+                output.display(),
+                inputs
+                    .iter()
+                    .map(|input| input.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })
+        .collect();
+    if !collisions.is_empty() {
+        return Err(anyhow!(
+            "several inputs map to the same output:\n{}",
+            collisions.join("\n")
+        ));
+    }
+
+    Ok(())
+}
+
+/// Check input file of specified configuration, and generate output dat file.
+pub(crate) fn convert(config: &Config) -> Result<Summary> {
+    config.input_archive()?;
+
+    generate_output(config)
+}
+
+/// Convert all specified configurations, running up to specified number of jobs in parallel.
+///
+/// Returns the result of each conversion, in configurations order.
+#[must_use]
+pub fn convert_all(configs: &[Config], jobs: usize) -> Vec<Result<Summary>> {
+    map_parallel(
+        configs,
+        jobs,
+        |job| format!("batch-{job}"),
+        |config| {
+            debug!("converting {}", config.input_file_path.display());
+            convert(config)
+        },
+    )
+}
+
+/// Status of a conversion, as displayed in summary table.
+fn status(result: &Result<Summary>) -> String {
+    match result {
+        Ok(summary) if summary.warnings.is_empty() => String::from("ok"),
+        Ok(summary) => format!(
+            "ok ({} warning{})",
+            summary.warnings.len(),
+            if summary.warnings.len() > 1 { "s" } else { "" }
+        ),
+        Err(err) => format!(
+            "failed: {}",
+            err.to_string().lines().next().unwrap_or_default()
+        ),
+    }
+}
+
+/// Format a table with input file, output file and status of each conversion.
+#[must_use]
+pub fn format_table(configs: &[Config], results: &[Result<Summary>]) -> String {
+    let rows: Vec<[String; 3]> = configs
+        .iter()
+        .zip(results)
+        .map(|(config, result)| {
+            [
+                config.input_file_path.display().to_string(),
+                config.output_file_path.display().to_string(),
+                status(result),
+            ]
+        })
+        .collect();
+    let header = [
+        String::from("Input"),
+        String::from("Output"),
+        String::from("Status"),
+    ];
+    let width = |column: usize| {
+        rows.iter()
+            .chain(iter::once(&header))
+            .map(|row| row[column].chars().count())
+            .max()
+            .unwrap_or_default()
+    };
+    let (input_width, output_width) = (width(0), width(1));
+
+    let mut table = String::new();
+    for [input, output, status] in iter::once(&header).chain(&rows) {
+        let _ = writeln!(
+            table,
+            "{input:input_width$}  {output:output_width$}  {status}"
+        );
+    }
+
+    table
+}
+
+/// Print a table with input file, output file and status of each conversion.
+pub fn print_table(configs: &[Config], results: &[Result<Summary>]) {
+    print!("{}", format_table(configs, results));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn it_finds_inputs() {
+        let dir = env::temp_dir().join("convert-mame-extras-romvault-batch");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("old")).unwrap();
+        for file in [
+            "MAME 0.265 EXTRAs.zip",
+            "MAME 0.264 EXTRAs.zip",
+            "notes.txt",
+            "old/MAME 0.262 EXTRAs.zip",
+        ] {
+            fs::write(dir.join(file), "").unwrap();
+        }
+
+        let profile = Profile::default();
+        let names = |inputs: Vec<Input>| -> Vec<String> {
+            inputs.into_iter().map(|input| input.file_name).collect()
+        };
+        assert_eq!(
+            vec!["MAME 0.264 EXTRAs.zip", "MAME 0.265 EXTRAs.zip"],
+            names(find_inputs(&dir, false, &profile).unwrap())
+        );
+        assert_eq!(
+            vec![
+                "MAME 0.264 EXTRAs.zip",
+                "MAME 0.265 EXTRAs.zip",
+                "MAME 0.262 EXTRAs.zip"
+            ],
+            names(find_inputs(&dir, true, &profile).unwrap())
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn it_skips_symlinked_dirs() {
+        let dir = env::temp_dir().join("convert-mame-extras-romvault-batch-symlink");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("sub/MAME 0.264 EXTRAs.zip"), "").unwrap();
+        std::os::unix::fs::symlink(&dir, dir.join("sub/loop")).unwrap();
+
+        let inputs = find_inputs(&dir, true, &Profile::default()).unwrap();
+        assert_eq!(
+            vec![dir.join("sub/MAME 0.264 EXTRAs.zip")],
+            inputs
+                .into_iter()
+                .map(|input| input.path)
+                .collect::<Vec<_>>()
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn it_detects_output_collisions() {
+        let config = |input: &str| {
+            let output = output_file_name(&Profile::default(), Some(0.264));
+            Config::new(
+                Path::new(input),
+                Some(Path::new(&output)),
+                Profile::default(),
+            )
+        };
+        assert!(check_collisions(&[config("MAME 0.264 EXTRAs.zip")]).is_ok());
+
+        let result = check_collisions(&[
+            config("a/MAME 0.264 EXTRAs.zip"),
+            config("b/MAME 0.264 EXTRAs.zip"),
+        ]);
+        assert!(result.is_err());
+        if let Err(err) = result {
+            assert_eq!(
+                "several inputs map to the same output:\nExtras 0.264.dat would be written from a/MAME 0.264 EXTRAs.zip, b/MAME 0.264 EXTRAs.zip",
+                err.to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn it_formats_status() {
+        assert_eq!("ok", status(&Ok(Summary::default())));
+        let summary = Summary {
+            warnings: vec![String::from("a"), String::from("b")],
+            ..Summary::default()
+        };
+        assert_eq!("ok (2 warnings)", status(&Ok(summary)));
+        assert_eq!(
+            "failed: duplicates found:",
+            status(&Err(anyhow!("duplicates found:\nduplicate game")))
+        );
+    }
+}
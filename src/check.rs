@@ -0,0 +1,176 @@
+//! Self-check of generated output, before it is written.
+//!
+//! The generated dat is parsed again to make sure it is well-formed, that `<dir>` and `<game>`
+//! tags are balanced and properly nested under the `<datafile>` root, and that it contains the
+//! expected number of games and roms. This figure is counted while reading source dats, minus
+//! the machines of unselected sections, the games and roms dropped by filters and by duplicates
+//! resolution, plus the games created by splitting rom paths. Rom checks never drop roms.
+
+use anyhow::anyhow;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::reader::Reader;
+use std::borrow::Cow;
+
+use crate::filter::Filtered;
+
+/// Custom result with any context error.
+type Result<T> = anyhow::Result<T>;
+
+/// Number of games and roms.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Counts {
+    /// Number of games
+    pub games: usize,
+    /// Number of roms
+    pub roms: usize,
+}
+
+impl Counts {
+    /// Add specified counts to these counts.
+    pub const fn add(&mut self, other: Self) {
+        self.games += other.games;
+        self.roms += other.roms;
+    }
+
+    /// Remove specified counts from these counts.
+    pub const fn remove(&mut self, other: Self) {
+        self.games = self.games.saturating_sub(other.games);
+        self.roms = self.roms.saturating_sub(other.roms);
+    }
+}
+
+impl From<Filtered> for Counts {
+    fn from(filtered: Filtered) -> Self {
+        Self {
+            games: filtered.games,
+            roms: filtered.roms,
+        }
+    }
+}
+
+/// Check that specified generated dat is well-formed and contains specified counts.
+///
+/// # Errors
+///
+/// Will return `Err` if dat is malformed, if dirs and games are not balanced or not properly
+/// nested, or if counts of games or roms do not match.
+pub fn check_output(content: &[u8], expected: Counts) -> Result<()> {
+    let mut reader = Reader::from_reader(content);
+    let mut open: Vec<Vec<u8>> = Vec::new();
+    let mut found = Counts::default();
+    let mut buf = Vec::new();
+
+    loop {
+        let event = reader.read_event_into(&mut buf).map_err(|err| {
+            anyhow!(
+                "generated output is malformed at position {}: {err}",
+                reader.error_position()
+            )
+        })?;
+        match event {
+            Event::Start(element) => {
+                count(&element, &open, &mut found)?;
+                open.push(element.name().as_ref().to_vec());
+            }
+            Event::Empty(element) => count(&element, &open, &mut found)?,
+            Event::End(_) => {
+                open.pop();
+            }
+            Event::Eof => break,
+            _ => (),
+        }
+        buf.clear();
+    }
+
+    if let Some(name) = open.last() {
+        return Err(anyhow!(
+            "generated output is malformed: `{}` is not closed",
+            String::from_utf8_lossy(name)
+        ));
+    }
+    if found != expected {
+        return Err(anyhow!(
+            "generated output contains {} games and {} roms, expected {} games and {} roms",
+            found.games,
+            found.roms,
+            expected.games,
+            expected.roms
+        ));
+    }
+
+    Ok(())
+}
+
+/// Count specified element, opened within specified elements, checking it is properly nested.
+fn count(element: &BytesStart, open: &[Vec<u8>], found: &mut Counts) -> Result<()> {
+    let name = element.name();
+    let parent = open.last().map(Vec::as_slice);
+    let nested = match name.as_ref() {
+        b"datafile" => parent.is_none(),
+        b"dir" | b"game" => matches!(parent, Some(b"datafile" | b"dir")),
+        b"rom" => parent == Some(b"game"),
+        _ => parent.is_some(),
+    };
+    if !nested {
+        return Err(anyhow!(
+            "generated output is malformed: unexpected `{}` in `{}`",
+            String::from_utf8_lossy(name.as_ref()),
+            parent.map_or_else(|| Cow::from("document"), String::from_utf8_lossy)
+        ));
+    }
+
+    match name.as_ref() {
+        b"game" => found.games += 1,
+        b"rom" => found.roms += 1,
+        _ => (),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAT: &str = r#"<?xml version="1.0"?><datafile><header><name>Extras</name></header><dir name="artwork"><game name="005"><description>005</description><rom name="a.png" size="10"/><rom name="b.png" size="bad"/></game></dir></datafile>"#;
+
+    const COUNTS: Counts = Counts { games: 1, roms: 2 };
+
+    #[test]
+    fn it_accepts_matching_output() {
+        assert!(check_output(DAT.as_bytes(), COUNTS).is_ok());
+    }
+
+    #[test]
+    fn it_rejects_count_mismatch() {
+        let expected = Counts { games: 2, ..COUNTS };
+        let err = check_output(DAT.as_bytes(), expected).unwrap_err();
+        assert_eq!(
+            "generated output contains 1 games and 2 roms, expected 2 games and 2 roms",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn it_rejects_unbalanced_tags() {
+        assert!(check_output(b"<datafile><dir name=\"a\"></datafile>", COUNTS).is_err());
+        let err = check_output(b"<datafile><dir name=\"a\">", Counts::default()).unwrap_err();
+        assert_eq!(
+            "generated output is malformed: `dir` is not closed",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn it_rejects_misplaced_elements() {
+        let err = check_output(
+            b"<datafile><game name=\"a\"><dir name=\"b\"/></game></datafile>",
+            Counts::default(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            "generated output is malformed: unexpected `dir` in `game`",
+            err.to_string()
+        );
+    }
+}
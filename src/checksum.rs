@@ -0,0 +1,284 @@
+//! Checksums of generated dats, written as sidecar files and in a manifest.
+//!
+//! Sidecar files are written next to the output file, named after it with an extra extension
+//! (e.g. `Extras.dat.sha256`), in the format of the usual command line tools (`sha256sum`,
+//! `md5sum`, SFV). The manifest is a JSON document tracing the output file back to its input.
+
+use anyhow::anyhow;
+use clap::ValueEnum;
+use md5::{Digest, Md5};
+use ring::digest::{Algorithm, Context, SHA1_FOR_LEGACY_USE_ONLY, SHA256};
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use crate::Config;
+
+/// Custom result with any context error.
+type Result<T> = anyhow::Result<T>;
+
+/// Checksum sidecar file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Checksum {
+    /// SHA-256 digest, in `sha256sum` format.
+    Sha256,
+    /// MD5 digest, in `md5sum` format.
+    Md5,
+    /// CRC-32, in Simple File Verification format.
+    Sfv,
+}
+
+impl Checksum {
+    /// Extension appended to output file name.
+    #[must_use]
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Sha256 => "sha256",
+            Self::Md5 => "md5",
+            Self::Sfv => "sfv",
+        }
+    }
+
+    /// Content of sidecar file of specified file with specified digests.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use convert_mame_extras_romvault::checksum::{Checksum, Digests};
+    ///
+    /// let digests = Digests { sha256: String::from("ba78"), md5: String::from("9001"), crc32: String::from("352441c2") };
+    /// assert_eq!("ba78  Extras.dat\n", Checksum::Sha256.sidecar("Extras.dat", &digests));
+    /// assert_eq!("Extras.dat 352441C2\n", Checksum::Sfv.sidecar("Extras.dat", &digests));
+    /// ```
+    #[must_use]
+    pub fn sidecar(self, file_name: &str, digests: &Digests) -> String {
+        match self {
+            Self::Sha256 => format!("{}  {file_name}\n", digests.sha256),
+            Self::Md5 => format!("{}  {file_name}\n", digests.md5),
+            Self::Sfv => format!("{file_name} {}\n", digests.crc32.to_uppercase()),
+        }
+    }
+}
+
+/// Digests of a file, hex encoded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Digests {
+    /// SHA-256 digest
+    pub sha256: String,
+    /// MD5 digest
+    pub md5: String,
+    /// CRC-32
+    pub crc32: String,
+}
+
+impl Digests {
+    /// Compute digests of specified file, reading it once.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if file cannot be read.
+    pub fn file(path: &Path) -> Result<Self> {
+        let mut sha256 = Context::new(&SHA256);
+        let mut md5 = Md5::new();
+        let mut crc32 = crc32fast::Hasher::new();
+        read_file(path, |bytes| {
+            sha256.update(bytes);
+            md5.update(bytes);
+            crc32.update(bytes);
+        })?;
+
+        Ok(Self {
+            sha256: hex(sha256.finish().as_ref()),
+            md5: hex(&md5.finalize()),
+            crc32: format!("{:08x}", crc32.finalize()),
+        })
+    }
+}
+
+/// Write specified checksum sidecar files of specified file.
+///
+/// Returns paths of written sidecar files.
+///
+/// # Errors
+///
+/// Will return `Err` if file cannot be read, or if a sidecar file cannot be written.
+pub fn write_sidecars(path: &Path, checksums: &[Checksum]) -> Result<Vec<PathBuf>> {
+    if checksums.is_empty() {
+        return Ok(Vec::new());
+    }
+    let digests = Digests::file(path)?;
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+
+    checksums
+        .iter()
+        .map(|checksum| {
+            let mut sidecar_path = path.as_os_str().to_owned();
+            sidecar_path.push(".");
+            sidecar_path.push(checksum.extension());
+            let sidecar_path = PathBuf::from(sidecar_path);
+            fs::write(&sidecar_path, checksum.sidecar(&file_name, &digests))
+                .map_err(|err| anyhow!("cannot write {}: {err}", sidecar_path.display()))?;
+            Ok(sidecar_path)
+        })
+        .collect()
+}
+
+/// Manifest of a conversion, tracing output file back to its input.
+#[derive(Debug, Serialize)]
+pub struct Manifest {
+    /// Input Zip file name
+    pub input_file: String,
+    /// SHA-256 digest of input file, hex encoded
+    pub input_sha256: String,
+    /// Name of converter
+    pub tool: &'static str,
+    /// Version of converter
+    pub tool_version: &'static str,
+    /// Version extracted from input file name
+    pub version: Option<f32>,
+    /// Output dat file name
+    pub output_file: String,
+    /// SHA-256 digest of output file, hex encoded
+    pub output_sha256: String,
+}
+
+impl Manifest {
+    /// Build manifest of conversion with specified configuration, once output file is written.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if input or output file cannot be read.
+    pub fn new(config: &Config) -> Result<Self> {
+        let file_name = |path: &Path| {
+            path.file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned()
+        };
+
+        Ok(Self {
+            input_file: file_name(&config.input_file_path),
+            input_sha256: sha256_file(&config.input_file_path)?,
+            tool: env!("CARGO_PKG_NAME"),
+            tool_version: env!("CARGO_PKG_VERSION"),
+            version: config.version,
+            output_file: file_name(&config.output_file_path),
+            output_sha256: sha256_file(&config.output_file_path)?,
+        })
+    }
+
+    /// Write manifest as JSON to specified file.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if manifest cannot be written.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)? + "\n";
+        fs::write(path, json).map_err(|err| anyhow!("cannot write {}: {err}", path.display()))
+    }
+}
+
+/// Compute SHA-256 digest of specified file, hex encoded.
+///
+/// # Errors
+///
+/// Will return `Err` if file cannot be read.
+pub fn sha256_file(path: &Path) -> Result<String> {
+    digest_file(path, &SHA256)
+}
+
+/// Compute SHA-1 digest of specified file, hex encoded.
+///
+/// # Errors
+///
+/// Will return `Err` if file cannot be read.
+pub fn sha1_file(path: &Path) -> Result<String> {
+    digest_file(path, &SHA1_FOR_LEGACY_USE_ONLY)
+}
+
+/// Compute digest of specified file with specified algorithm, hex encoded.
+fn digest_file(path: &Path, algorithm: &'static Algorithm) -> Result<String> {
+    let mut context = Context::new(algorithm);
+    read_file(path, |bytes| context.update(bytes))?;
+
+    Ok(hex(context.finish().as_ref()))
+}
+
+/// Read specified file, passing its content to specified function by blocks.
+fn read_file(path: &Path, mut f: impl FnMut(&[u8])) -> Result<()> {
+    let mut file =
+        File::open(path).map_err(|err| anyhow!("cannot read {}: {err}", path.display()))?;
+    let mut buffer = [0; 8192];
+    loop {
+        let count = match file.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(count) => count,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.into()),
+        };
+        f(&buffer[..count]);
+    }
+
+    Ok(())
+}
+
+/// Encode specified bytes as lowercase hexadecimal.
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn it_hashes_file() {
+        let path = env::temp_dir().join("convert-mame-extras-romvault-checksum.txt");
+        fs::write(&path, "abc").unwrap();
+        assert_eq!(
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            sha256_file(&path).unwrap()
+        );
+        assert_eq!(
+            "a9993e364706816aba3e25717850c26c9cd0d89d",
+            sha1_file(&path).unwrap()
+        );
+        assert_eq!(
+            Digests {
+                sha256: String::from(
+                    "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+                ),
+                md5: String::from("900150983cd24fb0d6963f7d28e17f72"),
+                crc32: String::from("352441c2"),
+            },
+            Digests::file(&path).unwrap()
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn it_writes_sidecars() {
+        let path = env::temp_dir().join("convert-mame-extras-romvault-sidecar.dat");
+        fs::write(&path, "abc").unwrap();
+        let sidecars = write_sidecars(&path, &[Checksum::Md5, Checksum::Sfv]).unwrap();
+        assert_eq!(2, sidecars.len());
+        assert_eq!(
+            "900150983cd24fb0d6963f7d28e17f72  convert-mame-extras-romvault-sidecar.dat\n",
+            fs::read_to_string(&sidecars[0]).unwrap()
+        );
+        assert_eq!(
+            "convert-mame-extras-romvault-sidecar.dat 352441C2\n",
+            fs::read_to_string(&sidecars[1]).unwrap()
+        );
+        for file in sidecars.iter().chain([&path]) {
+            fs::remove_file(file).unwrap();
+        }
+    }
+}
@@ -0,0 +1,256 @@
+//! Compression of output dats.
+//!
+//! Output dats can be written as a Zip file containing the dat, or compressed with gzip or
+//! Zstandard. Compression is selected from the extension of the output file, or explicitly.
+//! Dats read by other commands (e.g. `verify`) are decompressed according to their extension.
+//! The dat is generated and checked in memory first, so the whole content is buffered
+//! before it is compressed into the output file. Output is reproducible: gzip headers carry
+//! no timestamp, and Zip entries are dated on 1980-01-01, the earliest Zip date.
+
+use anyhow::anyhow;
+use clap::ValueEnum;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, DateTime, ZipArchive, ZipWriter};
+
+/// Custom result with any context error.
+type Result<T> = anyhow::Result<T>;
+
+/// Compression of output dat.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Compression {
+    /// Plain dat.
+    #[default]
+    None,
+    /// Zip file containing the dat.
+    Zip,
+    /// Dat compressed with gzip.
+    Gz,
+    /// Dat compressed with Zstandard.
+    Zst,
+}
+
+impl Compression {
+    /// Compression matching extension of specified output file (e.g. `.dat.gz`).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use convert_mame_extras_romvault::compress::Compression;
+    /// use std::path::Path;
+    ///
+    /// assert_eq!(Compression::Gz, Compression::from_path(Path::new("Extras.dat.gz")));
+    /// assert_eq!(Compression::Zip, Compression::from_path(Path::new("Extras.ZIP")));
+    /// assert_eq!(Compression::None, Compression::from_path(Path::new("Extras.dat")));
+    /// ```
+    #[must_use]
+    pub fn from_path(path: &Path) -> Self {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("zip") => Self::Zip,
+            Some("gz") => Self::Gz,
+            Some("zst") => Self::Zst,
+            _ => Self::None,
+        }
+    }
+
+    /// Extension added to output file name, if any.
+    #[must_use]
+    pub const fn extension(self) -> Option<&'static str> {
+        match self {
+            Self::None => None,
+            Self::Zip => Some("zip"),
+            Self::Gz => Some("gz"),
+            Self::Zst => Some("zst"),
+        }
+    }
+
+    /// Append extension of this compression to specified dat file name (e.g. `Extras.dat.gz`).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use convert_mame_extras_romvault::compress::Compression;
+    /// use std::path::{Path, PathBuf};
+    ///
+    /// assert_eq!(PathBuf::from("Extras.dat.zst"), Compression::Zst.append_extension(Path::new("Extras.dat")));
+    /// assert_eq!(PathBuf::from("Extras.dat"), Compression::None.append_extension(Path::new("Extras.dat")));
+    /// ```
+    #[must_use]
+    pub fn append_extension(self, path: &Path) -> PathBuf {
+        let mut path = path.as_os_str().to_owned();
+        if let Some(extension) = self.extension() {
+            path.push(".");
+            path.push(extension);
+        }
+
+        PathBuf::from(path)
+    }
+
+    /// Write specified dat content to specified file with this compression.
+    /// A Zip file contains a single entry with specified name, modified on 1980-01-01,
+    /// so that the same content gives the same file.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if content cannot be compressed or written.
+    pub fn write(self, content: &[u8], file: File, entry_name: &str) -> Result<()> {
+        let mut writer = BufWriter::new(file);
+        match self {
+            Self::None => {
+                writer.write_all(content)?;
+                writer.flush()?;
+            }
+            Self::Zip => {
+                let mut zip = ZipWriter::new(writer);
+                let options = SimpleFileOptions::default()
+                    .compression_method(CompressionMethod::Deflated)
+                    .large_file(u32::try_from(content.len()).is_err())
+                    .last_modified_time(DateTime::default());
+                zip.start_file(entry_name, options)?;
+                zip.write_all(content)?;
+                zip.finish()?.flush()?;
+            }
+            Self::Gz => {
+                let mut encoder = GzEncoder::new(writer, flate2::Compression::default());
+                encoder.write_all(content)?;
+                encoder.finish()?.flush()?;
+            }
+            Self::Zst => {
+                let mut encoder = zstd::Encoder::new(writer, 0)?;
+                encoder.write_all(content)?;
+                encoder.finish()?.flush()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Read dat content from specified file with this compression.
+    /// A Zip file must contain the dat as its first entry.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if file cannot be read or decompressed.
+    pub fn read(self, file: File) -> Result<Vec<u8>> {
+        let mut content = Vec::new();
+        match self {
+            Self::None => {
+                BufReader::new(file).read_to_end(&mut content)?;
+            }
+            Self::Zip => {
+                let mut zip = ZipArchive::new(BufReader::new(file))?;
+                zip.by_index(0)?.read_to_end(&mut content)?;
+            }
+            Self::Gz => {
+                GzDecoder::new(BufReader::new(file)).read_to_end(&mut content)?;
+            }
+            Self::Zst => {
+                zstd::Decoder::new(file)?.read_to_end(&mut content)?;
+            }
+        }
+
+        Ok(content)
+    }
+}
+
+/// Read content of specified dat file, decompressed according to its extension
+/// (see [`Compression::from_path`]).
+///
+/// # Errors
+///
+/// Will return `Err` if file cannot be read or decompressed.
+pub fn read_dat(path: &Path) -> Result<Vec<u8>> {
+    File::open(path)
+        .map_err(anyhow::Error::from)
+        .and_then(|file| Compression::from_path(path).read(file))
+        .map_err(|err| anyhow!("cannot read {}: {err}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+    use std::{env, fs};
+
+    use super::*;
+
+    const CONTENT: &[u8] = b"<datafile></datafile>";
+
+    fn write(compression: Compression, name: &str) -> Vec<u8> {
+        let path = env::temp_dir().join(name);
+        let _ = fs::remove_file(&path);
+        compression
+            .write(CONTENT, File::create(&path).unwrap(), "Extras 0.264.dat")
+            .unwrap();
+        let written = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        written
+    }
+
+    #[test]
+    fn it_writes_zip_entry() {
+        let written = write(
+            Compression::Zip,
+            "convert-mame-extras-romvault-compress.zip",
+        );
+        let mut archive = ZipArchive::new(Cursor::new(written)).unwrap();
+        let mut content = Vec::new();
+        archive
+            .by_name("Extras 0.264.dat")
+            .unwrap()
+            .read_to_end(&mut content)
+            .unwrap();
+        assert_eq!(CONTENT, content);
+    }
+
+    #[test]
+    fn it_writes_reproducible_zip() {
+        let name = "convert-mame-extras-romvault-compress-reproducible.zip";
+        let written = write(Compression::Zip, name);
+        let mut archive = ZipArchive::new(Cursor::new(written.clone())).unwrap();
+        assert_eq!(
+            Some(DateTime::default()),
+            archive.by_index(0).unwrap().last_modified()
+        );
+        assert_eq!(written, write(Compression::Zip, name));
+    }
+
+    #[test]
+    fn it_writes_gz() {
+        let written = write(Compression::Gz, "convert-mame-extras-romvault-compress.gz");
+        let mut content = Vec::new();
+        GzDecoder::new(written.as_slice())
+            .read_to_end(&mut content)
+            .unwrap();
+        assert_eq!(CONTENT, content);
+    }
+
+    #[test]
+    fn it_writes_zst() {
+        let written = write(
+            Compression::Zst,
+            "convert-mame-extras-romvault-compress.zst",
+        );
+        assert_eq!(CONTENT, zstd::decode_all(written.as_slice()).unwrap());
+    }
+
+    #[test]
+    fn it_reads_compressed_dats() {
+        for compression in Compression::value_variants() {
+            let path = compression
+                .append_extension(&env::temp_dir().join("convert-mame-extras-romvault-read.dat"));
+            let _ = fs::remove_file(&path);
+            compression
+                .write(CONTENT, File::create(&path).unwrap(), "Extras 0.264.dat")
+                .unwrap();
+            assert_eq!(CONTENT, read_dat(&path).unwrap(), "{compression:?}");
+            fs::remove_file(&path).unwrap();
+        }
+    }
+}
@@ -1,149 +1,395 @@
 //! Generation of dat files.
 
-use anyhow::{anyhow, Error};
+use anyhow::anyhow;
+use clap::ValueEnum;
+use log::{debug, log_enabled, trace, Level};
 use quick_xml::events::attributes::Attribute;
 use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event};
 use quick_xml::name::QName;
 use quick_xml::reader::Reader;
 use quick_xml::Writer;
 use std::borrow::Cow;
-use std::fs::File;
-use std::io::{BufReader, Cursor, ErrorKind, Write};
+use std::io::{BufRead, BufReader, Cursor, ErrorKind, Read, Write};
 use std::path::Path;
+use std::sync::Mutex;
 use std::thread::{Scope, ScopedJoinHandle};
-use std::{fs, thread, vec};
-use zip::read::ZipFile;
-use zip::ZipArchive;
-
-use crate::files::{ALL_NON_ZIPPED_CONTENT, ARTWORK, SAMPLES};
+use std::time::{Duration, Instant};
+use std::{fs, mem, slice, thread, vec};
+use time::PrimitiveDateTime;
+
+use crate::batch::output_file_name;
+use crate::check::{check_output, Counts};
+use crate::checksum::write_sidecars;
+use crate::compress::Compression;
+use crate::date::{format_date, resolve_date};
+use crate::dtd::Doctype;
+use crate::duplicates::resolve_duplicates;
+use crate::filter::{Filtered, Filters};
+use crate::mapping::Source;
+use crate::paths::split_paths;
+use crate::pool::map_parallel;
+use crate::profile::Header;
+use crate::progress::{ProgressCallback, Tracker};
+use crate::provenance::Provenance;
+use crate::report::{SectionStats, Stats};
+use crate::rom::{check_rom, RomCheck};
+use crate::sort::sort_nodes;
+use crate::tree::{display_name, Dir, Game, Node, Rom};
+use crate::zip::SharedArchive;
 use crate::Config;
 
 /// Custom result with any context error.
 type Result<T> = anyhow::Result<T>;
 
+/// Number of chunks of a source dat per job, so that jobs stay busy when chunks take unequal time.
+const CHUNKS_PER_JOB: usize = 4;
+
+/// Minimum size of a chunk of source dat parsed by a job.
+const MIN_CHUNK_SIZE: usize = 256 * 1024;
+
+/// Comment header of generated dats.
+const COMMENT: &str = "Compatible with RomVault";
+
+/// Minimum number of children of a dir serialized in parallel.
+const MIN_PARALLEL_CHILDREN: usize = 1024;
+
+/// Character used to indent output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum IndentChar {
+    /// Indent with spaces.
+    #[default]
+    Space,
+    /// Indent with tabs.
+    Tab,
+}
+
+impl IndentChar {
+    /// Byte written for each indentation level.
+    #[must_use]
+    pub const fn byte(self) -> u8 {
+        match self {
+            Self::Space => b' ',
+            Self::Tab => b'\t',
+        }
+    }
+}
+
+/// Indentation of output, each element being written on its own line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Indent {
+    /// Character repeated for each level
+    pub char: IndentChar,
+    /// Number of characters for each level
+    pub width: usize,
+}
+
 /// Game configuration for a specific input dat.
 struct GameConfig<'a> {
-    /// Optional root dir (for artwork and samples)
+    /// Optional root dir (e.g. for artwork and samples)
     root_dir: Option<&'a str>,
-    /// Optional directories (for dats and folders)
+    /// Optional directories (e.g. for dats and folders)
     dirs: Vec<&'a str>,
+    /// Source dat placement, deciding which machines belong to selected sections
+    source: &'a Source,
+    /// Sections to convert, all if `None`
+    sections: Option<&'a [String]>,
     /// Dat file name
     dat: &'a str,
-    /// Input Zip file path
-    zip: &'a Path,
+    /// Input Zip file, shared by all source dats
+    archive: &'a SharedArchive,
+    /// Optional validation of rom entries
+    rom_check: Option<RomCheck>,
+    /// Include and exclude filters
+    filters: &'a Filters,
+    /// Split rom paths into nested dirs and games
+    split_paths: bool,
+    /// Optional callback receiving progress updates
+    progress: Option<&'a ProgressCallback>,
+    /// Number of jobs parsing machines of the source dat
+    jobs: usize,
+}
+
+/// Output generated for a single input dat.
+#[derive(Default)]
+struct Section {
+    /// Generated dirs and games
+    nodes: Vec<Node>,
+    /// Warnings raised during generation
+    warnings: Vec<String>,
+    /// Games and roms read from source dat
+    read: Counts,
+    /// Games and roms of machines outside selected sections
+    skipped: Counts,
+    /// Games and roms dropped by filters
+    filtered: Filtered,
+    /// Games created by splitting rom paths
+    split: usize,
+    /// Time spent generating section
+    elapsed: Duration,
 }
 
-/// Dat content result from output generation.
-struct DatContent {
-    /// Content generated for all roms
-    all: String,
-    /// Content generated for artwork
-    artwork: String,
-    /// Content generated for samples
-    samples: String,
+/// Summary of output generation.
+#[derive(Default)]
+pub struct Summary {
+    /// Warnings raised during generation
+    pub warnings: Vec<String>,
+    /// Games and roms dropped by include and exclude filters
+    pub filtered: Filtered,
+    /// Statistics of each source dat
+    pub sections: Vec<SectionStats>,
+    /// Items written to output, after duplicates are resolved
+    pub totals: Stats,
+    /// Games and roms expected in output, counted from input (see [`check_output`])
+    pub expected: Counts,
 }
 
 /// Generate output file using dats from input Zip file.
 ///
+/// Returns a summary with warnings raised during generation and counts of filtered items.
+///
+/// # Errors
+///
+/// Will return `Err` if generation failed (see [`generate_content`]), if generated content
+/// does not pass self-check (see [`check_output`]), or if output or checksum files cannot be written.
+pub fn generate_output(config: &Config) -> Result<Summary> {
+    let (content, summary) = generate_content(config)?;
+    check_output(&content, summary.expected)?;
+    write_to_file(
+        &content,
+        &config.output_file_path,
+        config.compression,
+        &output_file_name(&config.profile, config.version),
+    )?;
+    for sidecar in write_sidecars(&config.output_file_path, &config.checksums)? {
+        debug!("wrote {}", sidecar.display());
+    }
+
+    Ok(summary)
+}
+
+/// Generate output dat content using dats from input Zip file, without writing it.
+///
+/// Returns the generated dat, and a summary with warnings raised during generation and counts of filtered items.
+///
 /// # Errors
 ///
-/// Will return `Err` if an error occured during XML read or XML write.
-pub fn generate_output(config: &Config) -> Result<()> {
-    let mut writer = Writer::new(Cursor::new(Vec::new()));
+/// Will return `Err` if an error occured during XML read or XML write,
+/// if a malformed rom is found while roms are checked with [`RomCheck::Error`],
+/// or if duplicates are found with [`crate::duplicates::Duplicates::Error`] policy.
+pub fn generate_content(config: &Config) -> Result<(Vec<u8>, Summary)> {
+    let mut writer = new_writer(config.indent);
 
     // Declaration
     add_declaration(&mut writer)?;
 
     // Doctype
-    add_doctype(&mut writer)?;
+    add_doctype(&mut writer, config.doctype)?;
 
     // Add start tag for datafile
     writer.write_event(Event::Start(BytesStart::new("datafile")))?;
 
     // Add headers
-    add_headers(&mut writer, config.version)?;
-
-    let result = thread::scope(|scope| -> Result<DatContent> {
-        // Spawn thread to compute all content
-        let config_all = Box::new(GameConfig {
-            root_dir: None,
-            dirs: vec!["dats", "folders"],
-            dat: ALL_NON_ZIPPED_CONTENT,
-            zip: &config.input_file_path,
-        });
-        let all = build_handle(scope, config_all)?;
-
-        // Spawn thread to compute artwork
-        let config_artwork = Box::new(GameConfig {
-            root_dir: Some("artwork"),
-            dirs: Vec::new(),
-            dat: ARTWORK,
-            zip: &config.input_file_path,
-        });
-        let artwork = build_handle(scope, config_artwork)?;
-
-        // Spawn thread to compute samples
-        let config_samples = Box::new(GameConfig {
-            root_dir: Some("samples"),
-            dirs: Vec::new(),
-            dat: SAMPLES,
-            zip: &config.input_file_path,
-        });
-        let samples = build_handle(scope, config_samples)?;
-
-        let Ok(join_all_result) = all.join() else {
-            return Err(Error::msg("Failed to generate all content"));
-        };
-        let all = join_all_result.unwrap_or_default();
+    let archive = config.input_archive()?;
+    let comment = if config.provenance {
+        format!("{COMMENT}; {}", Provenance::new(config, &archive)?)
+    } else {
+        String::from(COMMENT)
+    };
+    let entries: Vec<&str> = config
+        .profile
+        .mapping
+        .selected_sources(config.sections.as_deref())
+        .iter()
+        .map(|source| source.entry.as_str())
+        .collect();
+    let date = resolve_date(config.date, &archive, &entries)?;
+    add_headers(
+        &mut writer,
+        &config.profile.header,
+        config.version,
+        date.as_ref(),
+        &comment,
+    )?;
 
-        let Ok(join_artwork_result) = artwork.join() else {
-            return Err(Error::msg("Failed to generate artwork content"));
-        };
-        let artwork = join_artwork_result.unwrap_or_default();
+    let sections = thread::scope(|scope| -> Result<Vec<(&str, Section)>> {
+        // Spawn a thread to compute each selected dat
+        let mut handles = Vec::new();
+        for game_config in game_configs(config, &archive) {
+            let dat = game_config.dat;
+            handles.push((dat, build_handle(scope, Box::new(game_config))?));
+        }
 
-        let Ok(join_samples_result) = samples.join() else {
-            return Err(Error::msg("Failed to generate samples content"));
-        };
-        let samples = join_samples_result.unwrap_or_default();
+        handles
+            .into_iter()
+            .map(|(dat, handle)| {
+                let Ok(join_result) = handle.join() else {
+                    return Err(anyhow!("Failed to generate content for {dat}"));
+                };
+                join_result.map(|section| (dat, section))
+            })
+            .collect()
+    })?;
+
+    // Gather threads results
+    let mut nodes = Vec::new();
+    let mut summary = Summary::default();
+    for (dat, section) in sections {
+        summary.sections.push(SectionStats {
+            dat: String::from(dat),
+            stats: Stats::count(&section.nodes),
+            elapsed_ms: section.elapsed.as_millis(),
+        });
+        nodes.extend(section.nodes);
+        summary.warnings.extend(section.warnings);
+        summary.filtered.add(section.filtered);
+        summary.expected.add(section.read);
+        summary.expected.remove(section.skipped);
+        summary.expected.remove(section.filtered.into());
+        summary.expected.games += section.split;
+    }
 
-        Ok(DatContent {
-            all,
-            artwork,
-            samples,
-        })
-    });
+    // Check duplicates across all sections
+    let resolution = resolve_duplicates(&mut nodes, config.duplicates)?;
+    summary.warnings.extend(resolution.report);
+    summary.expected.remove(resolution.dropped);
+    summary.totals = Stats::count(&nodes);
+    if config.sort {
+        sort_nodes(&mut nodes);
+    }
 
-    // Write threads results to main writer
-    let content = result?;
-    writer.write_event(Event::Text(BytesText::from_escaped(content.all)))?;
-    writer.write_event(Event::Text(BytesText::from_escaped(content.artwork)))?;
-    writer.write_event(Event::Text(BytesText::from_escaped(content.samples)))?;
+    // Write games to main writer. Indented output is written by a single job, as group
+    // writers cannot start at the indentation level of their parent dir.
+    let jobs = if config.indent.is_some() {
+        1
+    } else {
+        config.jobs
+    };
+    add_nodes_parallel(&mut writer, &nodes, jobs)?;
 
     // Add end tag for datafile
     writer.write_event(Event::End(BytesEnd::new("datafile")))?;
 
-    write_to_file(writer, &config.output_file_path)?;
+    let mut content = writer.into_inner().into_inner();
+    if config.indent.is_some() {
+        content.push(b'\n');
+    }
 
-    Ok(())
+    Ok((content, summary))
+}
+
+/// Create writer of output content, with optional indentation.
+fn new_writer(indent: Option<Indent>) -> Writer<Cursor<Vec<u8>>> {
+    let inner = Cursor::new(Vec::new());
+    match indent {
+        Some(indent) => Writer::new_with_indent(inner, indent.char.byte(), indent.width),
+        None => Writer::new(inner),
+    }
+}
+
+/// Build game configurations for each source dat required by selected sections.
+fn game_configs<'a>(config: &'a Config, archive: &'a SharedArchive) -> Vec<GameConfig<'a>> {
+    let sections = config.sections.as_deref();
+    let is_selected =
+        |section: &str| sections.is_none_or(|sections| sections.iter().any(|s| s == section));
+
+    config
+        .profile
+        .mapping
+        .selected_sources(sections)
+        .into_iter()
+        .map(|source| GameConfig {
+            root_dir: source.root_dir.as_deref(),
+            dirs: source
+                .dirs
+                .iter()
+                .map(String::as_str)
+                .filter(|dir| is_selected(dir))
+                .collect(),
+            source,
+            sections,
+            dat: &source.entry,
+            archive,
+            rom_check: config.rom_check,
+            filters: &config.filters,
+            split_paths: config.split_paths,
+            progress: config.progress.as_ref(),
+            jobs: config.jobs,
+        })
+        .collect()
 }
 
 /// Build thread handle in order to generate output for specified config in another thread.
+///
+/// With more than one job, the source dat is read in memory and split into chunks of machines,
+/// parsed in parallel by the configured number of jobs.
 fn build_handle<'a>(
     scope: &'a Scope<'a, '_>,
     config: Box<GameConfig<'a>>,
-) -> Result<ScopedJoinHandle<'a, Result<String>>> {
+) -> Result<ScopedJoinHandle<'a, Result<Section>>> {
     let thread_builder = thread::Builder::new().name(String::from(config.dat));
-    let handle = thread_builder.spawn_scoped(scope, move || -> Result<String> {
-        let mut writer = Writer::new(Cursor::new(Vec::new()));
-        let zip_file = File::open(config.zip)?;
-        let mut zip = ZipArchive::new(&zip_file)?;
-        let entry = zip.by_name(config.dat)?;
-        let mut reader = Reader::from_reader(BufReader::new(entry));
-
-        add_games(&mut writer, &config, &mut reader)?;
+    let handle = thread_builder.spawn_scoped(scope, move || -> Result<Section> {
+        let now = Instant::now();
+        let mut zip = config.archive.clone();
+        let mut entry = zip.by_name(config.dat)?;
+        debug!("reading {}, {} bytes", config.dat, entry.size());
+        let tracker = Mutex::new(Tracker::new(config.progress, config.dat, entry.size()));
+
+        let mut section = Section::default();
+        if config.jobs > 1 {
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content)?;
+            let chunks =
+                split_chunks(&content, chunk_size(content.len(), config.jobs), config.dat)?;
+            debug!("parsing {} chunks with {} jobs", chunks.len(), config.jobs);
+            let results = map_parallel(
+                &chunks,
+                config.jobs,
+                |job| format!("{}-{job}", config.dat),
+                |chunk| -> Result<Section> {
+                    let mut reader = Reader::from_reader(*chunk);
+                    let mut chunk_section = Section::default();
+                    add_games(&mut chunk_section, &config, &mut reader, &tracker)?;
+                    Ok(chunk_section)
+                },
+            );
+            for result in results {
+                let chunk_section = result?;
+                section.nodes.extend(chunk_section.nodes);
+                section.warnings.extend(chunk_section.warnings);
+                section.read.add(chunk_section.read);
+                section.skipped.add(chunk_section.skipped);
+                section.filtered.add(chunk_section.filtered);
+            }
+        } else {
+            let mut reader = Reader::from_reader(BufReader::new(entry));
+            add_games(&mut section, &config, &mut reader, &tracker)?;
+        }
+        if let Ok(mut tracker) = tracker.lock() {
+            tracker.finish();
+        }
 
-        let result = writer.into_inner().into_inner();
-        Ok(String::from_utf8(result).unwrap_or_default())
+        if let Some(root_dir) = config.root_dir {
+            // Root dir is dropped if all its games were filtered
+            if !section.nodes.is_empty() || section.filtered.games == 0 {
+                let children = mem::take(&mut section.nodes);
+                section.nodes.push(Node::Dir(Dir {
+                    name: String::from(root_dir),
+                    children,
+                }));
+            }
+        }
+        if config.split_paths {
+            section.split = split_paths(&mut section.nodes);
+        }
+        section.elapsed = now.elapsed();
+        debug!(
+            "generated {} nodes with {} warnings in {:.2?}",
+            section.nodes.len(),
+            section.warnings.len(),
+            section.elapsed
+        );
+
+        Ok(section)
     });
 
     if let Err(err) = handle {
@@ -153,11 +399,73 @@ fn build_handle<'a>(
     Ok(handle.unwrap())
 }
 
-/// Add games for the specified configuration.
-fn add_games(
-    writer: &mut Writer<Cursor<Vec<u8>>>,
+/// Size of chunks to parse in parallel with specified number of jobs, for a source dat of specified size.
+fn chunk_size(len: usize, jobs: usize) -> usize {
+    (len / (jobs * CHUNKS_PER_JOB)).max(MIN_CHUNK_SIZE)
+}
+
+/// Split specified source dat into chunks of about specified size, each ending after a top-level
+/// element of the datafile (e.g. a machine), as found by the XML reader.
+///
+/// The first chunk contains the declaration and the header. When the source dat is split,
+/// the end of the datafile following the last element is dropped, so that each chunk can be
+/// parsed on its own.
+///
+/// # Errors
+///
+/// Will return `Err` if source dat is not a valid XML document.
+fn split_chunks<'a>(content: &'a [u8], chunk_size: usize, dat: &str) -> Result<Vec<&'a [u8]>> {
+    let mut reader = Reader::from_reader(content);
+    let mut chunks = Vec::new();
+    let mut depth = 0_usize;
+    let mut start = 0;
+    let mut last_end = 0;
+    loop {
+        let event = reader.read_event().map_err(|err| {
+            anyhow!(
+                "{dat}: malformed XML at position {}: {err}",
+                reader.error_position()
+            )
+        })?;
+        let closes_element = match event {
+            Event::Start(_) => {
+                depth += 1;
+                false
+            }
+            Event::End(_) => {
+                depth = depth.saturating_sub(1);
+                depth == 1
+            }
+            Event::Empty(_) => depth == 1,
+            Event::Eof => break,
+            _ => false,
+        };
+        if closes_element {
+            // Position of the reader is right after the element
+            last_end = usize::try_from(reader.buffer_position())?;
+            if last_end - start >= chunk_size {
+                chunks.push(&content[start..last_end]);
+                start = last_end;
+            }
+        }
+    }
+
+    // Last chunk
+    let end = if start > 0 { last_end } else { content.len() };
+    if end > start {
+        chunks.push(&content[start..end]);
+    }
+
+    Ok(chunks)
+}
+
+/// Add games for the specified configuration to section, applying filters.
+/// Progress is reported to tracker after each machine.
+fn add_games<R: BufRead>(
+    section: &mut Section,
     config: &GameConfig,
-    reader: &mut Reader<BufReader<ZipFile<'_, &File>>>,
+    reader: &mut Reader<R>,
+    tracker: &Mutex<Tracker>,
 ) -> Result<()> {
     /// Helper state to parse input dat
     enum State {
@@ -170,138 +478,290 @@ fn add_games(
     }
     let mut buf = Vec::new();
     let mut state = State::Datafile;
-    let dirs = &config.dirs;
-    let mut close_dir = false;
-    let root_dir = config.root_dir;
-    let dir = "dir";
-
-    if root_dir.is_some() {
-        let mut dir = BytesStart::new(dir);
-        let attr = Attribute {
-            key: QName(b"name"),
-            value: Cow::from(root_dir.unwrap().as_bytes()),
-        };
-        dir.push_attribute(attr);
-        writer.write_event(Event::Start(dir))?;
-    }
+    let mut game: Option<Game> = None;
+    let mut last_position = 0;
 
     loop {
         match (&state, reader.read_event_into(&mut buf)) {
-            (State::Datafile, Ok(Event::Start(tag))) => {
-                if tag.name().as_ref() == b"machine" {
-                    state = State::Machine;
-                    let name_attribute = tag.try_get_attribute("name")?.unwrap();
-                    if !dirs.is_empty() {
-                        let value = String::from_utf8(name_attribute.value.to_vec())?;
-
-                        if dirs.contains(&value.as_str()) {
-                            let mut dir = BytesStart::new(dir);
-                            dir.push_attribute(name_attribute.clone());
-                            writer.write_event(Event::Start(dir))?;
-                            close_dir = true;
-                        }
-                    }
-
-                    let mut game = BytesStart::new("game");
-                    game.push_attribute(name_attribute);
-                    writer.write_event(Event::Start(game))?;
-                }
+            (State::Datafile, Ok(Event::Start(tag))) if tag.name().as_ref() == b"machine" => {
+                state = State::Machine;
+                let name = tag
+                    .try_get_attribute("name")
+                    .ok()
+                    .flatten()
+                    .and_then(|name| String::from_utf8(name.value.into_owned()).ok())
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "{}: machine without valid name at position {}",
+                            config.dat,
+                            reader.buffer_position()
+                        )
+                    })?;
+                game = Some(Game {
+                    name,
+                    description: None,
+                    roms: Vec::new(),
+                    source: String::from(config.dat),
+                });
             }
-            (State::Machine, Ok(Event::Start(e))) => {
-                if e.name().as_ref() == b"description" {
-                    state = State::Description;
-                    writer.write_event(Event::Start(e))?;
+            (State::Machine, Ok(Event::Start(e))) if e.name().as_ref() == b"description" => {
+                state = State::Description;
+                if let Some(game) = game.as_mut() {
+                    game.description = Some(String::new());
                 }
             }
             (State::Description, Ok(Event::Text(e))) => {
-                writer.write_event(Event::Text(e))?;
-            }
-            (State::Machine, Ok(Event::Empty(e))) => {
-                if e.name().as_ref() == b"rom" {
-                    writer.write_event(Event::Empty(e))?;
+                if let Some(description) = game.as_mut().and_then(|game| game.description.as_mut())
+                {
+                    description.push_str(&String::from_utf8_lossy(&e));
                 }
             }
-            (State::Description, Ok(Event::End(e))) => {
-                if e.name().as_ref() == b"description" {
-                    state = State::Machine;
-                    writer.write_event(Event::End(e))?;
+            (State::Machine, Ok(Event::Empty(e))) if e.name().as_ref() == b"rom" => {
+                if let Some(game) = game.as_mut() {
+                    let element = match config.rom_check {
+                        Some(rom_check) => {
+                            check_game_rom(&e, game, config.dat, rom_check, &mut section.warnings)?
+                        }
+                        None => e.into_owned(),
+                    };
+                    game.roms.push(Rom { element });
                 }
             }
-            (State::Machine, Ok(Event::End(e))) => {
-                if e.name().as_ref() == b"machine" {
-                    state = State::Datafile;
-                    let game = BytesEnd::new("game");
-                    writer.write_event(Event::End(game))?;
-                    if close_dir {
-                        close_dir = false;
-                        let dir = BytesEnd::new(dir);
-                        writer.write_event(Event::End(dir))?;
-                    }
+            (State::Description, Ok(Event::End(e))) if e.name().as_ref() == b"description" => {
+                state = State::Machine;
+            }
+            (State::Machine, Ok(Event::End(e))) if e.name().as_ref() == b"machine" => {
+                state = State::Datafile;
+                if let Some(machine) = game.take() {
+                    section.read.add(Counts {
+                        games: 1,
+                        roms: machine.roms.len(),
+                    });
+                    add_game_node(section, config, machine);
+                }
+                let position = reader.buffer_position();
+                if let Ok(mut tracker) = tracker.lock() {
+                    tracker.machine(position - last_position);
                 }
+                last_position = position;
             }
             (_, Ok(Event::Eof)) => break,
-            (_, Err(err)) => panic!("Error at position {}: {:?}", reader.buffer_position(), err),
+            (_, Err(err)) => {
+                return Err(anyhow!(
+                    "{}: malformed XML at position {}: {err}",
+                    config.dat,
+                    reader.error_position()
+                ))
+            }
             _ => (),
         }
         buf.clear();
     }
 
-    if root_dir.is_some() {
-        let dir = BytesEnd::new(dir);
-        writer.write_event(Event::End(dir))?;
+    Ok(())
+}
+
+/// Add specified game to section nodes if it belongs to selected sections and is not dropped by filters.
+/// Games matching configured dirs are wrapped in a dir with the same name.
+fn add_game_node(section: &mut Section, config: &GameConfig, game: Game) {
+    if !config.source.converts(&game.name, config.sections) {
+        trace!("machine `{}` skipped, section not selected", game.name);
+        section.skipped.add(Counts {
+            games: 1,
+            roms: game.roms.len(),
+        });
+        return;
+    }
+    let is_dir = config.dirs.contains(&game.name.as_str());
+    let dir_path = if is_dir {
+        display_name(&game.name)
+    } else {
+        config.root_dir.map(String::from).unwrap_or_default()
+    };
+    let traced = log_enabled!(Level::Trace).then(|| (game.name.clone(), game.roms.len()));
+    let (kept, filtered) = config.filters.apply(game, &dir_path);
+    if let Some((name, roms)) = traced {
+        trace!(
+            "machine `{name}`: {roms} roms, {} filtered{}",
+            filtered.roms,
+            if kept.is_none() { ", game dropped" } else { "" }
+        );
+    }
+    section.filtered.add(filtered);
+    match kept {
+        Some(game) if is_dir => section.nodes.push(Node::Dir(Dir {
+            name: game.name.clone(),
+            children: vec![Node::Game(game)],
+        })),
+        Some(game) => section.nodes.push(Node::Game(game)),
+        None => (),
+    }
+}
+
+/// Check specified rom of specified game, and return the normalized rom.
+///
+/// Problems are added to warnings, or returned as an error with [`RomCheck::Error`].
+fn check_game_rom(
+    rom: &BytesStart,
+    game: &Game,
+    dat: &str,
+    rom_check: RomCheck,
+    warnings: &mut Vec<String>,
+) -> Result<BytesStart<'static>> {
+    let (normalized, problems) = check_rom(rom);
+    let rom_name = rom
+        .try_get_attribute("name")?
+        .map(|name| String::from_utf8_lossy(&name.value).into_owned())
+        .unwrap_or_default();
+    for problem in problems {
+        let message = format!("{dat}, game `{}`, rom `{rom_name}`: {problem}", game.name);
+        if rom_check == RomCheck::Error {
+            return Err(anyhow!(message));
+        }
+        warnings.push(message);
+    }
+
+    Ok(normalized)
+}
+
+/// Add specified dirs and games to writer.
+fn add_nodes(writer: &mut Writer<Cursor<Vec<u8>>>, nodes: &[Node]) -> Result<()> {
+    for node in nodes {
+        match node {
+            Node::Dir(dir) => {
+                let name = "dir";
+                let mut start = BytesStart::new(name);
+                start.push_attribute(Attribute {
+                    key: QName(b"name"),
+                    value: Cow::from(dir.name.as_bytes()),
+                });
+                writer.write_event(Event::Start(start))?;
+                add_nodes(writer, &dir.children)?;
+                writer.write_event(Event::End(BytesEnd::new(name)))?;
+            }
+            Node::Game(game) => add_game(writer, game)?,
+        }
     }
 
     Ok(())
 }
 
+/// Add specified dirs and games to writer, serializing children of large dirs with specified number of jobs.
+fn add_nodes_parallel(
+    writer: &mut Writer<Cursor<Vec<u8>>>,
+    nodes: &[Node],
+    jobs: usize,
+) -> Result<()> {
+    for node in nodes {
+        match node {
+            Node::Dir(dir) if jobs > 1 && dir.children.len() >= MIN_PARALLEL_CHILDREN => {
+                let name = "dir";
+                let mut start = BytesStart::new(name);
+                start.push_attribute(Attribute {
+                    key: QName(b"name"),
+                    value: Cow::from(dir.name.as_bytes()),
+                });
+                writer.write_event(Event::Start(start))?;
+
+                let groups: Vec<&[Node]> = dir
+                    .children
+                    .chunks(dir.children.len().div_ceil(jobs * CHUNKS_PER_JOB))
+                    .collect();
+                let buffers = map_parallel(
+                    &groups,
+                    jobs,
+                    |job| format!("writer-{job}"),
+                    |group| -> Result<Vec<u8>> {
+                        let mut group_writer = Writer::new(Cursor::new(Vec::new()));
+                        add_nodes(&mut group_writer, group)?;
+                        Ok(group_writer.into_inner().into_inner())
+                    },
+                );
+                for buffer in buffers {
+                    let buffer = buffer?;
+                    writer.get_mut().write_all(&buffer)?;
+                }
+
+                writer.write_event(Event::End(BytesEnd::new(name)))?;
+            }
+            _ => add_nodes(writer, slice::from_ref(node))?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Add specified game, with its description and roms, to writer.
+fn add_game(writer: &mut Writer<Cursor<Vec<u8>>>, game: &Game) -> Result<()> {
+    let name = "game";
+    let mut start = BytesStart::new(name);
+    start.push_attribute(Attribute {
+        key: QName(b"name"),
+        value: Cow::from(game.name.as_bytes()),
+    });
+    writer.write_event(Event::Start(start))?;
+
+    if let Some(description) = &game.description {
+        let name = "description";
+        writer.write_event(Event::Start(BytesStart::new(name)))?;
+        writer.write_event(Event::Text(BytesText::from_escaped(description.as_str())))?;
+        writer.write_event(Event::End(BytesEnd::new(name)))?;
+    }
+
+    for rom in &game.roms {
+        writer.write_event(Event::Empty(rom.element.borrow()))?;
+    }
+
+    writer.write_event(Event::End(BytesEnd::new(name)))?;
+
+    Ok(())
+}
+
 /// Add XML declaration to writer
-fn add_declaration(writer: &mut Writer<Cursor<Vec<u8>>>) -> Result<()> {
+pub(crate) fn add_declaration(writer: &mut Writer<Cursor<Vec<u8>>>) -> Result<()> {
     let declaration = BytesDecl::new("1.0", Some("UTF-8"), None);
     writer.write_event(Event::Decl(declaration))?;
 
     Ok(())
 }
 
-/// Add XML doctype to writer
-fn add_doctype(writer: &mut Writer<Cursor<Vec<u8>>>) -> Result<()> {
-    // TODO Update logiqx original DTD which doesn't support dir tags
-    /// Doctype to write to XML output
-    const DOCTYPE: &str = "datafile PUBLIC \"-//Logiqx//DTD ROM Management Datafile//EN\" \"http://www.logiqx.com/Dats/datafile.dtd\"";
-    let doctype = BytesText::from_escaped(DOCTYPE);
-    writer.write_event(Event::DocType(doctype))?;
+/// Add specified XML doctype to writer
+pub(crate) fn add_doctype(writer: &mut Writer<Cursor<Vec<u8>>>, doctype: Doctype) -> Result<()> {
+    let declaration = doctype.declaration();
+    writer.write_event(Event::DocType(BytesText::from_escaped(
+        declaration.as_str(),
+    )))?;
 
     Ok(())
 }
 
-/// Add all expected headers to writer
-fn add_headers(writer: &mut Writer<Cursor<Vec<u8>>>, version: Option<f32>) -> Result<()> {
+/// Add all expected headers to writer, with optional date and specified comment
+fn add_headers(
+    writer: &mut Writer<Cursor<Vec<u8>>>,
+    header: &Header,
+    version: Option<f32>,
+    date: Option<&PrimitiveDateTime>,
+    comment: &str,
+) -> Result<()> {
     let name = "header";
     writer.write_event(Event::Start(BytesStart::new(name)))?;
-    add_header(writer, "name", "Extras")?;
-
-    let description = if version.is_some() {
-        format!("MAME {} Extras (all content)", version.unwrap())
-    } else {
-        String::from("MAME Extras (all content)")
-    };
-
-    add_header(writer, "description", description.as_str())?;
+    add_header(writer, "name", &header.name)?;
+    add_header(writer, "description", &header.description(version))?;
     add_header(writer, "category", "Standard DatFile")?;
-    if version.is_some() {
-        add_header(writer, "version", &version.unwrap().to_string())?;
+    if let Some(version) = version {
+        add_header(writer, "version", &version.to_string())?;
+    }
+    if let Some(date) = date {
+        add_header(writer, "date", &format_date(date))?;
     }
-    add_header(writer, "author", "Pleasuredome")?;
+    add_header(writer, "author", &header.author)?;
     add_header(
         writer,
         "homepage",
         "https://github.com/fragoulin/convert-mame-extras-romvault",
     )?;
-    add_header(
-        writer,
-        "url",
-        "https://pleasuredome.miraheze.org/wiki/MAME_EXTRAs",
-    )?;
-    add_header(writer, "comment", "Compatible with RomVault")?;
+    add_header(writer, "url", &header.url)?;
+    add_header(writer, "comment", comment)?;
     writer.write_event(Event::End(BytesEnd::new(name)))?;
 
     Ok(())
@@ -316,15 +776,20 @@ fn add_header(writer: &mut Writer<Cursor<Vec<u8>>>, name: &str, value: &str) ->
     Ok(())
 }
 
-/// Write generated dat to specified output file.
-fn write_to_file(writer: Writer<Cursor<Vec<u8>>>, output_file_path: &Path) -> Result<()> {
-    let result = writer.into_inner().into_inner();
+/// Write generated dat to specified output file, with specified compression.
+/// A Zip output file contains the dat as a single entry with specified name.
+pub(crate) fn write_to_file(
+    content: &[u8],
+    output_file_path: &Path,
+    compression: Compression,
+    entry_name: &str,
+) -> Result<()> {
     let file_result = fs::OpenOptions::new()
         .create_new(true)
         .write(true)
         .open(output_file_path);
 
-    let mut file = match file_result {
+    let file = match file_result {
         Ok(file) => file,
         Err(err) => match err.kind() {
             ErrorKind::AlreadyExists => {
@@ -337,7 +802,169 @@ fn write_to_file(writer: Writer<Cursor<Vec<u8>>>, output_file_path: &Path) -> Re
         },
     };
 
-    file.write_all(&result)?;
+    compression.write(content, file, entry_name)
+}
 
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::File;
+    use std::path::PathBuf;
+
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    use crate::files::{ALL_NON_ZIPPED_CONTENT, ARTWORK, SAMPLES};
+    use crate::profile::Profile;
+
+    use super::*;
+
+    const ALL_CONTENT: &str = r#"<datafile><machine name="ctrlr"><description>ctrlr</description><rom name="a.cfg" size="1"/></machine><machine name="dats"><description>dats</description><rom name="history.xml" size="2"/></machine><machine name="folders"><description>folders</description><rom name="category.ini" size="3"/></machine></datafile>"#;
+
+    const EMPTY_DAT: &str = "<datafile></datafile>";
+
+    /// Write an input Zip file in temp dir with specified name and source dats, and build its configuration.
+    fn input_config(name: &str, all_content: &str) -> (PathBuf, Config) {
+        let path = env::temp_dir().join(name);
+        let mut zip = ZipWriter::new(File::create(&path).unwrap());
+        for (entry, content) in [
+            (ALL_NON_ZIPPED_CONTENT, all_content),
+            (ARTWORK, EMPTY_DAT),
+            (SAMPLES, EMPTY_DAT),
+        ] {
+            zip.start_file(entry, SimpleFileOptions::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+        let config = Config::new(&path, None, Profile::default());
+
+        (path, config)
+    }
+
+    /// Generate content with specified configuration, as a string.
+    fn generate(config: &Config) -> Result<String> {
+        let (content, _) = generate_content(config)?;
+        Ok(String::from_utf8(content)?)
+    }
+
+    const DAT: &[u8] = br#"<datafile><header><name>a</name></header><machine name="1"><rom name="a" size="1"/></machine><machine name="2"><rom name="b" size="1"/></machine><machine name="3"><rom name="c" size="1"/></machine></datafile>"#;
+
+    #[test]
+    fn it_splits_chunks_after_machines() {
+        let chunks = split_chunks(DAT, 50, "a.dat").unwrap();
+        assert_eq!(3, chunks.len());
+        assert!(chunks[0].starts_with(b"<datafile>"));
+        assert!(chunks[0].ends_with(b"</machine>"));
+        assert_eq!(
+            br#"<machine name="2"><rom name="b" size="1"/></machine>"#,
+            chunks[1]
+        );
+        assert_eq!(
+            br#"<machine name="3"><rom name="c" size="1"/></machine>"#,
+            chunks[2]
+        );
+    }
+
+    #[test]
+    fn it_ignores_machine_end_tags_in_values() {
+        let dat = br#"<datafile><machine name="1"><description>&lt;/machine&gt;</description><rom name="a" size="1"/></machine><!-- </machine> --><machine name="2"><rom name="b" size="1"/></machine></datafile>"#;
+        let chunks = split_chunks(dat, 10, "a.dat").unwrap();
+        assert_eq!(2, chunks.len());
+        assert!(chunks[0].ends_with(b"<rom name=\"a\" size=\"1\"/></machine>"));
+        assert!(chunks[1].starts_with(b"<!-- </machine> -->"));
+        assert!(chunks[1].ends_with(b"</machine>"));
+    }
+
+    #[test]
+    fn it_indents_nodes() {
+        let mut rom = BytesStart::new("rom");
+        rom.push_attribute(("name", "a.png"));
+        let nodes = vec![Node::Dir(Dir {
+            name: String::from("artwork"),
+            children: vec![Node::Game(Game {
+                name: String::from("005"),
+                description: Some(String::from("Zero &amp; Five")),
+                roms: vec![Rom { element: rom }],
+                source: String::from("artwork.dat"),
+            })],
+        })];
+        let indent = Indent {
+            char: IndentChar::Tab,
+            width: 1,
+        };
+
+        let mut writer = new_writer(Some(indent));
+        add_nodes_parallel(&mut writer, &nodes, 1).unwrap();
+        assert_eq!(
+            "<dir name=\"artwork\">\n\t<game name=\"005\">\n\t\t<description>Zero &amp; Five</description>\n\t\t<rom name=\"a.png\"/>\n\t</game>\n</dir>",
+            String::from_utf8(writer.into_inner().into_inner()).unwrap()
+        );
+    }
+
+    #[test]
+    fn it_keeps_small_dats_in_a_single_chunk() {
+        assert_eq!(
+            vec![DAT],
+            split_chunks(DAT, chunk_size(DAT.len(), 8), "a.dat").unwrap()
+        );
+    }
+
+    #[test]
+    fn it_counts_expected_games_from_input() {
+        let (path, mut config) =
+            input_config("convert-mame-extras-romvault-expected.zip", ALL_CONTENT);
+        config.sections = Some(vec![String::from("dats"), String::from("folders")]);
+        config.filters.exclude = vec!["folders".parse().unwrap()];
+        let (content, summary) = generate_content(&config).unwrap();
+        assert_eq!(Counts { games: 2, roms: 2 }, summary.expected);
+        assert!(check_output(&content, summary.expected).is_ok());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn it_converts_only_selected_sections() {
+        let (path, mut config) =
+            input_config("convert-mame-extras-romvault-sections.zip", ALL_CONTENT);
+        let content = generate(&config).unwrap();
+        assert!(content.contains(r#"<game name="ctrlr">"#));
+
+        config.sections = Some(vec![String::from("dats")]);
+        let content = generate(&config).unwrap();
+        assert!(content.contains(r#"<dir name="dats"><game name="dats">"#));
+        assert!(content.contains(r#"<game name="ctrlr">"#));
+        assert!(!content.contains(r#"<game name="folders">"#));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn it_returns_error_on_machine_without_name() {
+        let (path, mut config) = input_config(
+            "convert-mame-extras-romvault-unnamed.zip",
+            r"<datafile><machine><description>a</description></machine></datafile>",
+        );
+        assert_eq!(
+            "all_non-zipped_content.dat: machine without valid name at position 19",
+            generate(&config).unwrap_err().to_string()
+        );
+
+        config.jobs = 2;
+        assert!(generate(&config).is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn it_returns_error_on_malformed_input() {
+        let (path, mut config) = input_config(
+            "convert-mame-extras-romvault-malformed.zip",
+            r#"<datafile><machine name="a"></game></datafile>"#,
+        );
+        let err = generate(&config).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("all_non-zipped_content.dat: malformed XML at position"));
+
+        config.jobs = 2;
+        assert!(generate(&config).is_err());
+        fs::remove_file(path).unwrap();
+    }
 }
@@ -0,0 +1,233 @@
+//! Date of generated dats, written to their header.
+//!
+//! By default, the date is the latest modification time of the converted source dats in the
+//! input Zip file, so that regenerated dats stay byte-identical unless the input changes.
+//! The current time can be used instead, or no date at all. Following reproducible builds
+//! conventions, the value of `SOURCE_DATE_EPOCH` (seconds since Unix epoch) replaces the date
+//! of every source when set.
+
+use anyhow::anyhow;
+use clap::ValueEnum;
+use log::warn;
+use std::env;
+use time::{OffsetDateTime, PrimitiveDateTime};
+
+use crate::zip::SharedArchive;
+
+/// Custom result with any context error.
+type Result<T> = anyhow::Result<T>;
+
+/// Environment variable replacing the date of every source.
+pub const SOURCE_DATE_EPOCH: &str = "SOURCE_DATE_EPOCH";
+
+/// Source of the date written to headers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum DateSource {
+    /// Latest modification time of converted source dats in input Zip file.
+    #[default]
+    Input,
+    /// Current time.
+    Now,
+    /// No date header.
+    None,
+}
+
+/// Resolve date of specified source from specified input Zip file and converted entries.
+/// `SOURCE_DATE_EPOCH` replaces the date of every source when set.
+///
+/// Without selected source, the input date is used if available: the dat is written without
+/// date, with a warning, if no converted entry has a valid modification time.
+///
+/// # Errors
+///
+/// Will return `Err` if an entry is missing, if `SOURCE_DATE_EPOCH` is not a valid timestamp,
+/// or if [`DateSource::Input`] is selected and no entry has a valid modification time.
+pub fn resolve_date(
+    source: Option<DateSource>,
+    archive: &SharedArchive,
+    entries: &[&str],
+) -> Result<Option<PrimitiveDateTime>> {
+    let epoch = env::var(SOURCE_DATE_EPOCH).ok();
+    resolve_with(
+        source,
+        archive,
+        entries,
+        epoch.as_deref().filter(|epoch| !epoch.is_empty()),
+    )
+}
+
+/// Resolve date of specified source, with specified `SOURCE_DATE_EPOCH` value if any.
+fn resolve_with(
+    source: Option<DateSource>,
+    archive: &SharedArchive,
+    entries: &[&str],
+    source_date_epoch: Option<&str>,
+) -> Result<Option<PrimitiveDateTime>> {
+    match (source, source_date_epoch) {
+        (Some(DateSource::None), _) => Ok(None),
+        (_, Some(_)) | (Some(DateSource::Now), None) => now(source_date_epoch).map(Some),
+        (Some(DateSource::Input), None) => input_date(archive, entries)?
+            .ok_or_else(|| anyhow!("no modification time found in input file"))
+            .map(Some),
+        (None, None) => {
+            let date = input_date(archive, entries)?;
+            if date.is_none() {
+                warn!("no modification time found in input file, no date written");
+            }
+            Ok(date)
+        }
+    }
+}
+
+/// Latest modification time of specified entries of specified input Zip file, if any is valid.
+fn input_date(archive: &SharedArchive, entries: &[&str]) -> Result<Option<PrimitiveDateTime>> {
+    let mut archive = archive.clone();
+    let mut latest = None;
+    for entry in entries {
+        let modified = archive
+            .by_name(entry)?
+            .last_modified()
+            .and_then(|modified| PrimitiveDateTime::try_from(modified).ok());
+        latest = latest.max(modified);
+    }
+
+    Ok(latest)
+}
+
+/// Current UTC time, or time specified by a `SOURCE_DATE_EPOCH` value.
+fn now(source_date_epoch: Option<&str>) -> Result<PrimitiveDateTime> {
+    let now = match source_date_epoch {
+        Some(epoch) => {
+            let seconds = epoch
+                .trim()
+                .parse::<i64>()
+                .map_err(|err| anyhow!("invalid {SOURCE_DATE_EPOCH} `{epoch}`: {err}"))?;
+            OffsetDateTime::from_unix_timestamp(seconds)
+                .map_err(|err| anyhow!("invalid {SOURCE_DATE_EPOCH} `{epoch}`: {err}"))?
+        }
+        None => OffsetDateTime::now_utc(),
+    };
+
+    Ok(PrimitiveDateTime::new(now.date(), now.time()))
+}
+
+/// Format specified date as written to headers.
+///
+/// # Examples
+///
+/// ```
+/// use convert_mame_extras_romvault::date::format_date;
+/// use time::macros::datetime;
+///
+/// assert_eq!("2024-03-27", format_date(&datetime!(2024-03-27 12:00)));
+/// ```
+#[must_use]
+pub fn format_date(date: &PrimitiveDateTime) -> String {
+    format!(
+        "{:04}-{:02}-{:02}",
+        date.year(),
+        u8::from(date.month()),
+        date.day()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use zip::write::SimpleFileOptions;
+
+    use crate::zip::{open_input_file, InputAccess};
+
+    use super::*;
+
+    #[test]
+    fn it_uses_source_date_epoch() {
+        assert_eq!("1970-01-01", format_date(&now(Some("0")).unwrap()));
+        assert_eq!(
+            "2024-03-27",
+            format_date(&now(Some(" 1711540800\n")).unwrap())
+        );
+        assert!(now(Some("yesterday")).is_err());
+    }
+
+    /// Open a Zip file containing `artwork.dat`, modified on 2024-03-27 or with an invalid date.
+    fn archive(name: &str, valid_date: bool) -> SharedArchive {
+        let path = env::temp_dir().join(name);
+        let mut zip = zip::ZipWriter::new(fs::File::create(&path).unwrap());
+        let modified = zip::DateTime::from_date_and_time(2024, 3, 27, 12, 0, 0).unwrap();
+        zip.start_file(
+            "artwork.dat",
+            SimpleFileOptions::default().last_modified_time(modified),
+        )
+        .unwrap();
+        zip.finish().unwrap();
+        if !valid_date {
+            // Clear DOS time and date of local and central headers
+            let mut content = fs::read(&path).unwrap();
+            for (signature, offset) in [(b"PK\x03\x04", 10), (b"PK\x01\x02", 12)] {
+                let start = content
+                    .windows(4)
+                    .position(|window| window == signature)
+                    .unwrap();
+                content[start + offset..start + offset + 4].fill(0);
+            }
+            fs::write(&path, content).unwrap();
+        }
+        let archive = open_input_file(&path, &[], InputAccess::Read).unwrap();
+        fs::remove_file(&path).unwrap();
+        archive
+    }
+
+    fn resolve(
+        archive: &SharedArchive,
+        source: Option<DateSource>,
+        epoch: Option<&str>,
+    ) -> Result<Option<String>> {
+        resolve_with(source, archive, &["artwork.dat"], epoch)
+            .map(|date| date.map(|date| format_date(&date)))
+    }
+
+    #[test]
+    fn it_resolves_each_source() {
+        let archive = archive("date_it_resolves_each_source.zip", true);
+        let date = |date: &str| Some(String::from(date));
+        for source in [None, Some(DateSource::Input)] {
+            assert_eq!(date("2024-03-27"), resolve(&archive, source, None).unwrap());
+            assert_eq!(
+                date("1970-01-01"),
+                resolve(&archive, source, Some("0")).unwrap()
+            );
+        }
+        assert_eq!(
+            None,
+            resolve(&archive, Some(DateSource::None), None).unwrap()
+        );
+        assert_eq!(
+            None,
+            resolve(&archive, Some(DateSource::None), Some("0")).unwrap()
+        );
+        assert_eq!(
+            date("1970-01-01"),
+            resolve(&archive, Some(DateSource::Now), Some("0")).unwrap()
+        );
+    }
+
+    #[test]
+    fn it_writes_no_date_without_input_date_by_default() {
+        let archive = archive("date_it_writes_no_date_without_input_date.zip", false);
+        assert_eq!(None, resolve(&archive, None, None).unwrap());
+        assert_eq!(
+            "no modification time found in input file",
+            resolve(&archive, Some(DateSource::Input), None)
+                .unwrap_err()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn it_uses_current_time() {
+        let before = OffsetDateTime::now_utc().date();
+        assert!(now(None).unwrap().date() >= before);
+    }
+}
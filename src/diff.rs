@@ -0,0 +1,287 @@
+//! Differences between games of two dats.
+//!
+//! Games are identified by their path (e.g. `artwork/005`), built from the names of their dirs.
+//! Roms and other entries of a game (e.g. disks) are identified by their element and name,
+//! and compared with all their attributes. Games declared several times with the same path
+//! are merged. Paths are compared case-sensitively, so `a` and `A` are reported as distinct games.
+
+use anyhow::anyhow;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::reader::Reader;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::BufRead;
+use std::path::Path;
+
+use crate::compress::read_dat;
+use crate::tree::display_name;
+
+/// Custom result with any context error.
+type Result<T> = anyhow::Result<T>;
+
+/// Content of a game read from a dat.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct DatGame {
+    /// Unescaped description
+    pub description: Option<String>,
+    /// Entries (e.g. `rom 005.png`), with their raw attributes
+    pub entries: BTreeMap<String, String>,
+}
+
+/// Difference between two dats.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Change {
+    /// Game only present in new dat.
+    Added(String),
+    /// Game only present in old dat.
+    Removed(String),
+    /// Game present in both dats with a different content.
+    Changed {
+        /// Game path
+        path: String,
+        /// Description of each difference
+        details: Vec<String>,
+    },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Added(path) => write!(f, "+ {path}"),
+            Self::Removed(path) => write!(f, "- {path}"),
+            Self::Changed { path, details } => write!(f, "~ {path} ({})", details.join(", ")),
+        }
+    }
+}
+
+/// Read all games of specified dat, by path.
+///
+/// # Errors
+///
+/// Will return `Err` if dat is not a valid XML document.
+pub fn read_games<R: BufRead>(
+    mut reader: Reader<R>,
+    source: &str,
+) -> Result<BTreeMap<String, DatGame>> {
+    let mut games: BTreeMap<String, DatGame> = BTreeMap::new();
+    let mut dirs: Vec<String> = Vec::new();
+    let mut game: Option<String> = None;
+    let mut in_description = false;
+    let mut buf = Vec::new();
+
+    loop {
+        let event = reader
+            .read_event_into(&mut buf)
+            .map_err(|err| anyhow!("{source} at position {}: {err}", reader.error_position()))?;
+        match event {
+            Event::Start(e) if game.is_none() && e.name().as_ref() == b"dir" => {
+                dirs.push(name(&e)?);
+            }
+            Event::End(e) if game.is_none() && e.name().as_ref() == b"dir" => {
+                dirs.pop();
+            }
+            Event::Start(e) if game.is_none() && is_game(e.name().as_ref()) => {
+                let path = dirs
+                    .iter()
+                    .map(String::as_str)
+                    .chain([name(&e)?.as_str()])
+                    .collect::<Vec<_>>()
+                    .join("/");
+                games.entry(path.clone()).or_default();
+                game = Some(path);
+            }
+            Event::End(e) if is_game(e.name().as_ref()) => game = None,
+            Event::Start(e) if game.is_some() && e.name().as_ref() == b"description" => {
+                in_description = true;
+            }
+            Event::End(e) if e.name().as_ref() == b"description" => in_description = false,
+            Event::Text(e) if in_description => {
+                if let Some(game) = game.as_ref().and_then(|path| games.get_mut(path)) {
+                    let text = display_name(&String::from_utf8_lossy(&e));
+                    game.description
+                        .get_or_insert_with(String::new)
+                        .push_str(&text);
+                }
+            }
+            Event::Empty(e) if game.is_some() => {
+                let key = format!(
+                    "{} {}",
+                    String::from_utf8_lossy(e.name().as_ref()),
+                    name(&e)?
+                );
+                let attributes = String::from_utf8_lossy(e.attributes_raw())
+                    .trim()
+                    .to_string();
+                if let Some(game) = game.as_ref().and_then(|path| games.get_mut(path)) {
+                    game.entries.insert(key, attributes);
+                }
+            }
+            Event::Eof => break,
+            _ => (),
+        }
+        buf.clear();
+    }
+
+    Ok(games)
+}
+
+/// Check if specified element name is the name of a game.
+fn is_game(name: &[u8]) -> bool {
+    name == b"game" || name == b"machine"
+}
+
+/// Unescaped name attribute of specified element.
+fn name(element: &BytesStart) -> Result<String> {
+    Ok(element
+        .try_get_attribute("name")?
+        .map(|name| display_name(&String::from_utf8_lossy(&name.value)))
+        .unwrap_or_default())
+}
+
+/// Compare games of two dats.
+///
+/// Returns changes sorted by game path.
+#[must_use]
+pub fn diff_games(old: &BTreeMap<String, DatGame>, new: &BTreeMap<String, DatGame>) -> Vec<Change> {
+    let mut changes = Vec::new();
+    let mut paths: Vec<&String> = old.keys().chain(new.keys()).collect();
+    paths.sort();
+    paths.dedup();
+
+    for path in paths {
+        match (old.get(path), new.get(path)) {
+            (Some(_), None) => changes.push(Change::Removed(path.clone())),
+            (None, Some(_)) => changes.push(Change::Added(path.clone())),
+            (Some(old_game), Some(new_game)) if old_game != new_game => {
+                changes.push(Change::Changed {
+                    path: path.clone(),
+                    details: game_details(old_game, new_game),
+                });
+            }
+            _ => (),
+        }
+    }
+
+    changes
+}
+
+/// Describe differences between two versions of a game.
+fn game_details(old: &DatGame, new: &DatGame) -> Vec<String> {
+    let mut details = Vec::new();
+    if old.description != new.description {
+        details.push(String::from("description changed"));
+    }
+    for (key, attributes) in &new.entries {
+        match old.entries.get(key) {
+            None => details.push(format!("{key} added")),
+            Some(old_attributes) if old_attributes != attributes => {
+                details.push(format!("{key} changed"));
+            }
+            _ => (),
+        }
+    }
+    for key in old.entries.keys() {
+        if !new.entries.contains_key(key) {
+            details.push(format!("{key} removed"));
+        }
+    }
+
+    details
+}
+
+/// Compare games of two dat files.
+///
+/// # Errors
+///
+/// Will return `Err` if a dat cannot be read or is not a valid XML document.
+pub fn diff_dats(old: &Path, new: &Path) -> Result<Vec<Change>> {
+    let read = |path: &Path| {
+        let content = read_dat(path)?;
+        read_games(
+            Reader::from_reader(content.as_slice()),
+            &path.display().to_string(),
+        )
+    };
+
+    Ok(diff_games(&read(old)?, &read(new)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use crate::compress::Compression;
+
+    use super::*;
+
+    const OLD: &str = r#"<datafile><header><name>Extras</name></header><dir name="artwork"><game name="005"><description>005</description><rom name="005.png" size="1" crc="00000000"/><rom name="default.lay" size="2"/></game><game name="10yard"><description>10-Yard</description><rom name="a.png" size="1"/></game></dir></datafile>"#;
+
+    const NEW: &str = r#"<datafile><header><name>Extras</name></header><dir name="artwork"><game name="005"><description>005 &amp; co</description><rom name="005.png" size="1" crc="ffffffff"/><rom name="back.png" size="3"/></game><game name="1942"><description>1942</description><rom name="a.png" size="1"/></game></dir></datafile>"#;
+
+    fn games(dat: &str) -> BTreeMap<String, DatGame> {
+        read_games(Reader::from_str(dat), "test.dat").unwrap()
+    }
+
+    #[test]
+    fn it_reads_games_by_path() {
+        let games = games(OLD);
+        assert_eq!(
+            vec!["artwork/005", "artwork/10yard"],
+            games.keys().collect::<Vec<_>>()
+        );
+        let game = &games["artwork/005"];
+        assert_eq!(Some(String::from("005")), game.description);
+        assert_eq!(
+            Some(&String::from(r#"name="005.png" size="1" crc="00000000""#)),
+            game.entries.get("rom 005.png")
+        );
+    }
+
+    #[test]
+    fn it_finds_no_change_in_identical_dats() {
+        assert!(diff_games(&games(OLD), &games(OLD)).is_empty());
+    }
+
+    #[test]
+    fn it_diffs_games() {
+        let changes = diff_games(&games(OLD), &games(NEW));
+        let lines: Vec<String> = changes.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            vec![
+                "~ artwork/005 (description changed, rom 005.png changed, rom back.png added, rom default.lay removed)",
+                "- artwork/10yard",
+                "+ artwork/1942",
+            ],
+            lines
+        );
+    }
+
+    #[test]
+    fn it_merges_games_with_same_path() {
+        let dat = r#"<datafile><game name="a"><rom name="1" size="1"/></game><game name="A"><rom name="2" size="1"/></game><game name="a"><rom name="3" size="1"/></game></datafile>"#;
+        let games = games(dat);
+        assert_eq!(2, games.len());
+        assert_eq!(2, games["a"].entries.len());
+    }
+
+    #[test]
+    fn it_diffs_compressed_dats() {
+        let write = |dat: &str, name: &str| {
+            let path = env::temp_dir().join(name);
+            let _ = fs::remove_file(&path);
+            Compression::from_path(&path)
+                .write(
+                    dat.as_bytes(),
+                    fs::File::create(&path).unwrap(),
+                    "Extras.dat",
+                )
+                .unwrap();
+            path
+        };
+        let old = write(OLD, "convert-mame-extras-romvault-diff.dat.gz");
+        let new = write(NEW, "convert-mame-extras-romvault-diff.zip");
+        assert_eq!(3, diff_dats(&old, &new).unwrap().len());
+        fs::remove_file(old).unwrap();
+        fs::remove_file(new).unwrap();
+    }
+}
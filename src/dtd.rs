@@ -0,0 +1,487 @@
+//! Document type of generated dats, and validation of dats against the DTD named by their doctype.
+//!
+//! The Logiqx DTD referenced by default does not declare `<dir>`, so validating tools reject
+//! generated dats. [`DTD`] follows the Logiqx DTD for the elements written by the converter
+//! (including the `<machine>` alias of `<game>` found in MAME dats merged with Extras dats)
+//! and declares `<dir>` as a container of dirs and games. It is embedded in generated dats as
+//! an internal subset with [`Doctype::Inline`].
+//!
+//! Validation is not a general DTD validator: it checks the elements and attributes declared
+//! by [`DTD`], which are the ones this tool writes or copies. `<dir>` is only accepted if the
+//! doctype embeds a DTD declaring it, or if the dat has no doctype.
+
+use anyhow::anyhow;
+use clap::ValueEnum;
+use memchr::memchr_iter;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::reader::Reader;
+use std::path::Path;
+
+use crate::compress::read_dat;
+
+/// Custom result with any context error.
+type Result<T> = anyhow::Result<T>;
+
+/// Root element of dats.
+const ROOT: &str = "datafile";
+
+/// Public identifier of the Logiqx DTD.
+const LOGIQX_PUBLIC_ID: &str = "-//Logiqx//DTD ROM Management Datafile//EN";
+
+/// Doctype referencing the Logiqx DTD, which does not declare `<dir>`.
+const LOGIQX_DOCTYPE: &str = "datafile PUBLIC \"-//Logiqx//DTD ROM Management Datafile//EN\" \"http://www.logiqx.com/Dats/datafile.dtd\"";
+
+/// DTD of generated dats, declaring `<dir>`.
+pub const DTD: &str = r#"<!ELEMENT datafile (header?, (dir|game|machine)*)>
+<!ATTLIST datafile
+    build CDATA #IMPLIED
+    debug (yes|no) "no">
+<!ELEMENT header (name, description, category?, version?, date?, author, email?, homepage?, url?, comment?, clrmamepro?, romcenter?)>
+<!ELEMENT name (#PCDATA)>
+<!ELEMENT description (#PCDATA)>
+<!ELEMENT category (#PCDATA)>
+<!ELEMENT version (#PCDATA)>
+<!ELEMENT date (#PCDATA)>
+<!ELEMENT author (#PCDATA)>
+<!ELEMENT email (#PCDATA)>
+<!ELEMENT homepage (#PCDATA)>
+<!ELEMENT url (#PCDATA)>
+<!ELEMENT comment (#PCDATA)>
+<!ELEMENT clrmamepro EMPTY>
+<!ATTLIST clrmamepro
+    header CDATA #IMPLIED
+    forcemerging (none|split|full) "split"
+    forcenodump (obsolete|required|ignore) "obsolete"
+    forcepacking (zip|unzip) "zip">
+<!ELEMENT romcenter EMPTY>
+<!ATTLIST romcenter
+    plugin CDATA #IMPLIED
+    rommode (merged|split|unmerged) "split"
+    biosmode (merged|split|unmerged) "split"
+    samplemode (merged|unmerged) "merged"
+    lockrommode (yes|no) "no"
+    lockbiosmode (yes|no) "no"
+    locksamplemode (yes|no) "no">
+<!ELEMENT dir (dir|game|machine)*>
+<!ATTLIST dir
+    name CDATA #REQUIRED>
+<!ELEMENT game (comment*, description?, year?, manufacturer?, rom*, disk*, sample*)>
+<!ATTLIST game
+    name CDATA #REQUIRED
+    sourcefile CDATA #IMPLIED
+    isbios (yes|no) "no"
+    cloneof CDATA #IMPLIED
+    romof CDATA #IMPLIED
+    sampleof CDATA #IMPLIED>
+<!ELEMENT machine (comment*, description?, year?, manufacturer?, rom*, disk*, sample*)>
+<!ATTLIST machine
+    name CDATA #REQUIRED
+    sourcefile CDATA #IMPLIED
+    isbios (yes|no) "no"
+    cloneof CDATA #IMPLIED
+    romof CDATA #IMPLIED
+    sampleof CDATA #IMPLIED>
+<!ELEMENT year (#PCDATA)>
+<!ELEMENT manufacturer (#PCDATA)>
+<!ELEMENT rom EMPTY>
+<!ATTLIST rom
+    name CDATA #REQUIRED
+    size CDATA #REQUIRED
+    crc CDATA #IMPLIED
+    sha1 CDATA #IMPLIED
+    md5 CDATA #IMPLIED
+    merge CDATA #IMPLIED
+    status (baddump|nodump|good|verified) "good"
+    date CDATA #IMPLIED>
+<!ELEMENT disk EMPTY>
+<!ATTLIST disk
+    name CDATA #REQUIRED
+    sha1 CDATA #IMPLIED
+    md5 CDATA #IMPLIED
+    merge CDATA #IMPLIED
+    status (baddump|nodump|good|verified) "good">
+<!ELEMENT sample EMPTY>
+<!ATTLIST sample
+    name CDATA #REQUIRED>
+"#;
+
+/// Document type declaration written to generated dats.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Doctype {
+    /// Reference the Logiqx DTD, which does not declare `<dir>`.
+    #[default]
+    Logiqx,
+    /// Embed a DTD declaring `<dir>` as an internal subset.
+    Inline,
+}
+
+impl Doctype {
+    /// Content of the `<!DOCTYPE>` declaration.
+    #[must_use]
+    pub fn declaration(self) -> String {
+        match self {
+            Self::Logiqx => String::from(LOGIQX_DOCTYPE),
+            Self::Inline => format!("{ROOT} [\n{DTD}]"),
+        }
+    }
+}
+
+/// Allowed children of each element declared by [`DTD`]. Other elements only contain text or nothing.
+const CHILDREN: [(&str, &[&str]); 5] = [
+    (ROOT, &["header", "dir", "game", "machine"]),
+    (
+        "header",
+        &[
+            "name",
+            "description",
+            "category",
+            "version",
+            "date",
+            "author",
+            "email",
+            "homepage",
+            "url",
+            "comment",
+            "clrmamepro",
+            "romcenter",
+        ],
+    ),
+    ("dir", &["dir", "game", "machine"]),
+    ("game", &GAME_CHILDREN),
+    ("machine", &GAME_CHILDREN),
+];
+
+/// Allowed children of `game` and `machine`.
+const GAME_CHILDREN: [&str; 7] = [
+    "comment",
+    "description",
+    "year",
+    "manufacturer",
+    "rom",
+    "disk",
+    "sample",
+];
+
+/// Optional attributes of `game` and `machine`.
+const GAME_ATTRIBUTES: [&str; 5] = ["sourcefile", "isbios", "cloneof", "romof", "sampleof"];
+
+/// Required and optional attributes of each element declared by [`DTD`] with attributes.
+const ATTRIBUTES: [(&str, &[&str], &[&str]); 9] = [
+    (ROOT, &[], &["build", "debug"]),
+    (
+        "clrmamepro",
+        &[],
+        &["header", "forcemerging", "forcenodump", "forcepacking"],
+    ),
+    (
+        "romcenter",
+        &[],
+        &[
+            "plugin",
+            "rommode",
+            "biosmode",
+            "samplemode",
+            "lockrommode",
+            "lockbiosmode",
+            "locksamplemode",
+        ],
+    ),
+    ("dir", &["name"], &[]),
+    ("game", &["name"], &GAME_ATTRIBUTES),
+    ("machine", &["name"], &GAME_ATTRIBUTES),
+    (
+        "rom",
+        &["name", "size"],
+        &["crc", "sha1", "md5", "merge", "status", "date"],
+    ),
+    ("disk", &["name"], &["sha1", "md5", "merge", "status"]),
+    ("sample", &["name"], &[]),
+];
+
+/// Elements declared by [`DTD`] without content.
+const EMPTY_ELEMENTS: [&str; 5] = ["clrmamepro", "romcenter", "rom", "disk", "sample"];
+
+/// DTD named by the doctype of a dat.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum NamedDtd {
+    /// No doctype: elements and attributes are checked against [`DTD`].
+    None,
+    /// Logiqx DTD, which does not declare `<dir>`.
+    Logiqx,
+    /// Internal subset, declaring `<dir>` or not.
+    Inline {
+        /// Whether `<dir>` is declared
+        declares_dir: bool,
+    },
+}
+
+impl NamedDtd {
+    /// Identify DTD named by specified doctype content.
+    fn parse(doctype: &str) -> Result<Self> {
+        if let Some(subset) = doctype.find('[').map(|start| &doctype[start..]) {
+            let declares_dir = subset
+                .split("<!ELEMENT")
+                .skip(1)
+                .any(|declaration| declaration.split_whitespace().next() == Some("dir"));
+            return Ok(Self::Inline { declares_dir });
+        }
+        if doctype.contains(LOGIQX_PUBLIC_ID) || doctype.contains("datafile.dtd") {
+            return Ok(Self::Logiqx);
+        }
+
+        Err(anyhow!("cannot validate against unknown DTD `{doctype}`"))
+    }
+
+    /// Whether `<dir>` elements are allowed.
+    const fn allows_dir(self) -> bool {
+        match self {
+            Self::None => true,
+            Self::Logiqx => false,
+            Self::Inline { declares_dir } => declares_dir,
+        }
+    }
+}
+
+/// Validation state of a document.
+struct Validator {
+    /// DTD named by doctype
+    dtd: NamedDtd,
+    /// Names of open elements
+    stack: Vec<String>,
+    /// Whether a disallowed `<dir>` was already reported
+    dir_reported: bool,
+    /// Validity errors found so far
+    errors: Vec<String>,
+}
+
+impl Validator {
+    /// Check specified element, found at specified line, and open it.
+    fn start(&mut self, element: &BytesStart, line: usize) -> Result<()> {
+        let name = String::from_utf8_lossy(element.name().as_ref()).into_owned();
+        match self.stack.last() {
+            None if name != ROOT => {
+                self.error(line, &format!("root element `{name}` is not `{ROOT}`"));
+            }
+            None => (),
+            Some(parent) => {
+                let allowed = CHILDREN
+                    .iter()
+                    .find(|(element, _)| element == parent)
+                    .is_some_and(|(_, children)| children.contains(&name.as_str()));
+                if name == "dir" && allowed && !self.dtd.allows_dir() {
+                    if !self.dir_reported {
+                        self.dir_reported = true;
+                        self.error(
+                            line,
+                            "`dir` is not declared by the Logiqx DTD named by the doctype, embed a DTD declaring it (e.g. with `--doctype inline`)",
+                        );
+                    }
+                } else if !allowed {
+                    self.error(
+                        line,
+                        &format!("element `{name}` is not allowed in `{parent}`"),
+                    );
+                }
+            }
+        }
+
+        let declared = name == ROOT
+            || CHILDREN
+                .iter()
+                .any(|(_, children)| children.contains(&name.as_str()));
+        if !declared {
+            // Attributes of undeclared elements are not checked
+            self.stack.push(name);
+            return Ok(());
+        }
+        let (required, optional) = ATTRIBUTES
+            .iter()
+            .find(|(element, _, _)| *element == name)
+            .map_or((&[][..], &[][..]), |(_, required, optional)| {
+                (*required, *optional)
+            });
+        let mut names = Vec::new();
+        for attribute in element.attributes() {
+            let attribute = attribute?;
+            let key = String::from_utf8_lossy(attribute.key.as_ref()).into_owned();
+            if !required.contains(&key.as_str()) && !optional.contains(&key.as_str()) {
+                self.error(
+                    line,
+                    &format!("attribute `{key}` of `{name}` is not declared"),
+                );
+            }
+            names.push(key);
+        }
+        for attribute in required {
+            if !names.iter().any(|name| name == attribute) {
+                self.error(
+                    line,
+                    &format!("required attribute `{attribute}` of `{name}` is missing"),
+                );
+            }
+        }
+
+        self.stack.push(name);
+        Ok(())
+    }
+
+    /// Check text found at specified line in current element.
+    fn text(&mut self, text: &[u8], line: usize) {
+        if text.iter().all(u8::is_ascii_whitespace) {
+            return;
+        }
+        if let Some(element) = self.stack.last() {
+            if CHILDREN.iter().any(|(name, _)| name == element)
+                || EMPTY_ELEMENTS.contains(&element.as_str())
+            {
+                let message = format!("text is not allowed in `{element}`");
+                self.error(line, &message);
+            }
+        }
+    }
+
+    /// Record validity error found at specified line.
+    fn error(&mut self, line: usize, message: &str) {
+        self.errors.push(format!("line {line}: {message}"));
+    }
+}
+
+/// Validate specified document against the DTD named by its doctype.
+///
+/// Returns validity errors, prefixed with their line number.
+///
+/// # Errors
+///
+/// Will return `Err` if document is not well-formed, or if its doctype names an unknown DTD.
+pub fn validate(content: &[u8]) -> Result<Vec<String>> {
+    let mut reader = Reader::from_reader(content);
+    let mut validator = Validator {
+        dtd: NamedDtd::None,
+        stack: Vec::new(),
+        dir_reported: false,
+        errors: Vec::new(),
+    };
+    let mut buf = Vec::new();
+    let (mut line, mut position) = (1, 0);
+
+    loop {
+        // Count lines up to start of next event
+        let next = usize::try_from(reader.buffer_position())?;
+        line += memchr_iter(b'\n', &content[position..next]).count();
+        position = next;
+
+        let event = reader
+            .read_event_into(&mut buf)
+            .map_err(|err| anyhow!("line {line}: {err}"))?;
+        match event {
+            Event::DocType(doctype) => {
+                validator.dtd = NamedDtd::parse(&String::from_utf8_lossy(&doctype))?;
+            }
+            Event::Start(element) => validator.start(&element, line)?,
+            Event::Empty(element) => {
+                validator.start(&element, line)?;
+                validator.stack.pop();
+            }
+            Event::End(_) => {
+                validator.stack.pop();
+            }
+            Event::Text(text) => validator.text(&text, line),
+            Event::CData(text) => validator.text(&text, line),
+            Event::Eof => break,
+            _ => (),
+        }
+        buf.clear();
+    }
+
+    Ok(validator.errors)
+}
+
+/// Validate specified dat file (see [`validate`]).
+///
+/// # Errors
+///
+/// Will return `Err` if file cannot be read, is not well-formed, or if its doctype names an unknown DTD.
+pub fn validate_file(path: &Path) -> Result<Vec<String>> {
+    let content = read_dat(path)?;
+    validate(&content).map_err(|err| anyhow!("{}: {err}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inline(body: &str) -> String {
+        format!(
+            "<?xml version=\"1.0\"?>\n<!DOCTYPE {}>\n<datafile>{body}</datafile>",
+            Doctype::Inline.declaration()
+        )
+    }
+
+    const HEADER: &str = "<header><name>Extras</name><description>Extras</description><author>Pleasuredome</author></header>";
+
+    const DIRS: &str = "<dir name=\"artwork\"><dir name=\"sub\"><game name=\"005\"><description>005</description><rom name=\"a.png\" size=\"1\" crc=\"00000000\"/></game></dir></dir>";
+
+    #[test]
+    fn it_accepts_dirs_with_inline_dtd() {
+        let dat = inline(&format!("{HEADER}{DIRS}"));
+        assert_eq!(Vec::<String>::new(), validate(dat.as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn it_rejects_dirs_with_logiqx_dtd() {
+        let dat = format!(
+            "<?xml version=\"1.0\"?>\n<!DOCTYPE {LOGIQX_DOCTYPE}>\n<datafile>\n{HEADER}\n{DIRS}{DIRS}</datafile>"
+        );
+        assert_eq!(
+            vec!["line 5: `dir` is not declared by the Logiqx DTD named by the doctype, embed a DTD declaring it (e.g. with `--doctype inline`)"],
+            validate(dat.as_bytes()).unwrap()
+        );
+        let dat = dat.replace(DIRS, "");
+        assert_eq!(Vec::<String>::new(), validate(dat.as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn it_rejects_dirs_with_inline_dtd_not_declaring_them() {
+        let dat = format!(
+            "<!DOCTYPE datafile [\n<!ELEMENT datafile (game*)>\n]>\n<datafile>{DIRS}</datafile>"
+        );
+        assert_eq!(1, validate(dat.as_bytes()).unwrap().len());
+    }
+
+    #[test]
+    fn it_reports_invalid_content() {
+        let dat = format!(
+            "<datafile>\n<game name=\"005\"><rom name=\"a.png\">a</rom></game>\n{HEADER}<dir><rom name=\"b\" size=\"1\" serial=\"x\"/></dir><software name=\"c\"/></datafile>"
+        );
+        assert_eq!(
+            vec![
+                "line 2: required attribute `size` of `rom` is missing",
+                "line 2: text is not allowed in `rom`",
+                "line 3: required attribute `name` of `dir` is missing",
+                "line 3: element `rom` is not allowed in `dir`",
+                "line 3: attribute `serial` of `rom` is not declared",
+                "line 3: element `software` is not allowed in `datafile`",
+            ],
+            validate(dat.as_bytes()).unwrap()
+        );
+    }
+
+    #[test]
+    fn it_checks_root_element() {
+        let dat = format!("<!DOCTYPE {LOGIQX_DOCTYPE}><game name=\"a\"/>");
+        assert_eq!(
+            vec!["line 1: root element `game` is not `datafile`"],
+            validate(dat.as_bytes()).unwrap()
+        );
+    }
+
+    #[test]
+    fn it_fails_on_unknown_dtd() {
+        assert!(validate(b"<!DOCTYPE mame SYSTEM \"mame.dtd\"><mame/>").is_err());
+    }
+
+    #[test]
+    fn it_fails_on_malformed_document() {
+        assert!(validate(b"<datafile><dir></datafile>").is_err());
+    }
+}
@@ -0,0 +1,454 @@
+//! Detection of duplicate games and roms.
+//!
+//! Names are unescaped and compared case-insensitively, as Romvault usually
+//! maps dirs and games to a case-insensitive file system. Games are only
+//! compared to games, and dirs to dirs.
+//!
+//! Detection walks the whole generated tree once. It runs on every conversion
+//! with the default [`Duplicates::Warn`] policy, and is skipped with [`Duplicates::Ignore`].
+
+use anyhow::anyhow;
+use clap::ValueEnum;
+use std::collections::{HashMap, HashSet};
+use std::slice;
+
+use crate::check::Counts;
+use crate::report::Stats;
+use crate::tree::{display_name, Game, Node, Rom};
+
+/// Custom result with any context error.
+type Result<T> = anyhow::Result<T>;
+
+/// Policy applied when duplicate names are found.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Duplicates {
+    /// Stop the conversion and report all collisions.
+    Error,
+    /// Report collisions and keep all entries.
+    #[default]
+    Warn,
+    /// Keep the first entry for each name.
+    KeepFirst,
+    /// Keep the last entry for each name.
+    KeepLast,
+    /// Keep all entries, adding a numbered suffix to names already used.
+    Rename,
+    /// Skip detection.
+    Ignore,
+}
+
+/// Outcome of duplicate resolution.
+#[derive(Debug, Default)]
+pub struct Resolution {
+    /// Report line for each collision
+    pub report: Vec<String>,
+    /// Games and roms dropped, including games and roms of dropped dirs
+    pub dropped: Counts,
+}
+
+/// Check that game and dir names are unique within each dir, and rom names are unique within each game.
+/// Collisions are resolved according to specified policy.
+///
+/// Returns a report line for each collision, and counts of dropped entries.
+///
+/// # Errors
+///
+/// Will return `Err` listing every collision if policy is [`Duplicates::Error`].
+pub fn resolve_duplicates(nodes: &mut Vec<Node>, policy: Duplicates) -> Result<Resolution> {
+    let mut resolution = Resolution::default();
+    if policy == Duplicates::Ignore {
+        return Ok(resolution);
+    }
+    resolve_nodes(nodes, "", policy, &mut resolution);
+
+    if policy == Duplicates::Error && !resolution.report.is_empty() {
+        return Err(anyhow!(
+            "duplicates found:\n{}",
+            resolution.report.join("\n")
+        ));
+    }
+
+    Ok(resolution)
+}
+
+/// Resolve duplicates among specified sibling nodes, then in their children.
+fn resolve_nodes(
+    nodes: &mut Vec<Node>,
+    path: &str,
+    policy: Duplicates,
+    resolution: &mut Resolution,
+) {
+    let names: Vec<String> = nodes.iter().map(|node| node.name().to_string()).collect();
+    let sources: Vec<String> = nodes.iter().map(node_source).collect();
+    let kinds: Vec<&str> = nodes
+        .iter()
+        .map(|node| match node {
+            Node::Dir(_) => "dir",
+            Node::Game(_) => "game",
+        })
+        .collect();
+    let keep = resolve_names(&names, &kinds, policy, |index, first, new_name| {
+        resolution.report.push(format!(
+            "duplicate {} `{}` in `{}` ({} and {}){}",
+            kinds[index],
+            display_name(&names[index]),
+            display_path(path),
+            sources[first],
+            sources[index],
+            rename_note(new_name),
+        ));
+    });
+    for (node, _) in nodes
+        .iter()
+        .zip(&keep)
+        .filter(|(_, keep)| matches!(keep, Keep::No))
+    {
+        let stats = Stats::count(slice::from_ref(node));
+        resolution.dropped.add(Counts {
+            games: stats.games,
+            roms: stats.roms,
+        });
+    }
+    apply(nodes, &keep, |node, name| match node {
+        Node::Dir(dir) => dir.name = name,
+        Node::Game(game) => game.name = name,
+    });
+
+    for node in nodes.iter_mut() {
+        let node_path = join_path(path, node.name());
+        match node {
+            Node::Dir(dir) => resolve_nodes(&mut dir.children, &node_path, policy, resolution),
+            Node::Game(game) => resolve_roms(game, &node_path, policy, resolution),
+        }
+    }
+}
+
+/// Resolve duplicate rom names in specified game.
+fn resolve_roms(game: &mut Game, path: &str, policy: Duplicates, resolution: &mut Resolution) {
+    let names: Vec<String> = game.roms.iter().map(Rom::name).collect();
+    let kinds = vec!["rom"; names.len()];
+    let keep = resolve_names(&names, &kinds, policy, |index, _, new_name| {
+        resolution.report.push(format!(
+            "duplicate rom `{}` in game `{}` ({}){}",
+            display_name(&names[index]),
+            display_path(path),
+            game.source,
+            rename_note(new_name),
+        ));
+    });
+    resolution.dropped.roms += keep.iter().filter(|keep| matches!(keep, Keep::No)).count();
+    apply(&mut game.roms, &keep, |rom, name| rom.set_name(&name));
+}
+
+/// Outcome of duplicate resolution for a single entry.
+enum Keep {
+    /// Entry is kept as is.
+    Yes,
+    /// Entry is dropped.
+    No,
+    /// Entry is kept with a new escaped name.
+    Renamed(String),
+}
+
+/// Compute which entries to keep among specified escaped names.
+/// Only entries of the same kind are compared, by unescaped lowercase name.
+/// `on_duplicate` is called with the index of each duplicate entry, the index of the first
+/// entry with the same name, and the new name of the entry if it is renamed.
+fn resolve_names<F>(
+    names: &[String],
+    kinds: &[&str],
+    policy: Duplicates,
+    mut on_duplicate: F,
+) -> Vec<Keep>
+where
+    F: FnMut(usize, usize, Option<&str>),
+{
+    let key = |kind: &str, name: &str| format!("{kind}:{}", display_name(name).to_lowercase());
+    let mut keep: Vec<Keep> = names.iter().map(|_| Keep::Yes).collect();
+    let mut first_by_name: HashMap<String, usize> = HashMap::new();
+    let mut last_by_name: HashMap<String, usize> = HashMap::new();
+    let mut used: HashSet<String> = names
+        .iter()
+        .zip(kinds)
+        .map(|(name, kind)| key(kind, name))
+        .collect();
+
+    for (index, (name, kind)) in names.iter().zip(kinds).enumerate() {
+        let name_key = key(kind, name);
+        let Some(&first) = first_by_name.get(&name_key) else {
+            first_by_name.insert(name_key.clone(), index);
+            last_by_name.insert(name_key, index);
+            continue;
+        };
+
+        let new_name = match policy {
+            Duplicates::Rename => {
+                let new_name =
+                    numbered_name(name, |candidate| used.contains(&key(kind, candidate)));
+                used.insert(key(kind, &new_name));
+                Some(new_name)
+            }
+            _ => None,
+        };
+        on_duplicate(index, first, new_name.as_deref());
+
+        match policy {
+            Duplicates::Error | Duplicates::Warn | Duplicates::Ignore => (),
+            Duplicates::KeepFirst => keep[index] = Keep::No,
+            Duplicates::KeepLast => {
+                keep[last_by_name[&name_key]] = Keep::No;
+                last_by_name.insert(name_key, index);
+            }
+            Duplicates::Rename => keep[index] = Keep::Renamed(new_name.unwrap_or_default()),
+        }
+    }
+
+    keep
+}
+
+/// Drop or rename entries according to specified outcomes.
+fn apply<T, F>(entries: &mut Vec<T>, keep: &[Keep], mut rename: F)
+where
+    F: FnMut(&mut T, String),
+{
+    let mut outcomes = keep.iter();
+    let mut kept = Vec::with_capacity(entries.len());
+    for mut entry in entries.drain(..) {
+        match outcomes.next() {
+            Some(Keep::No) => (),
+            Some(Keep::Renamed(name)) => {
+                rename(&mut entry, name.clone());
+                kept.push(entry);
+            }
+            _ => kept.push(entry),
+        }
+    }
+    *entries = kept;
+}
+
+/// Build first available name with a numbered suffix, inserted before extension if any.
+fn numbered_name<F>(name: &str, is_used: F) -> String
+where
+    F: Fn(&str) -> bool,
+{
+    let (stem, extension) = match name.rfind('.') {
+        Some(index) if index > 0 => name.split_at(index),
+        _ => (name, ""),
+    };
+    (2..usize::MAX)
+        .map(|number| format!("{stem} ({number}){extension}"))
+        .find(|candidate| !is_used(candidate))
+        .unwrap_or_default()
+}
+
+/// Source dat of specified node, or a list of source dats for a dir.
+fn node_source(node: &Node) -> String {
+    match node {
+        Node::Game(game) => game.source.clone(),
+        Node::Dir(dir) => {
+            let mut sources: Vec<String> = Vec::new();
+            collect_sources(&dir.children, &mut sources);
+            sources.join(", ")
+        }
+    }
+}
+
+/// Collect distinct source dats of specified nodes.
+fn collect_sources(nodes: &[Node], sources: &mut Vec<String>) {
+    for node in nodes {
+        match node {
+            Node::Game(game) => {
+                if !sources.contains(&game.source) {
+                    sources.push(game.source.clone());
+                }
+            }
+            Node::Dir(dir) => collect_sources(&dir.children, sources),
+        }
+    }
+}
+
+/// Join escaped path and escaped name with a `/`.
+fn join_path(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.to_string()
+    } else {
+        format!("{path}/{name}")
+    }
+}
+
+/// Path for display, `/` for the datafile root.
+fn display_path(path: &str) -> String {
+    if path.is_empty() {
+        String::from("/")
+    } else {
+        display_name(path)
+    }
+}
+
+/// Note appended to report lines when an entry is renamed.
+fn rename_note(new_name: Option<&str>) -> String {
+    new_name.map_or_else(String::new, |name| {
+        format!(", renamed to `{}`", display_name(name))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use quick_xml::events::BytesStart;
+
+    use crate::tree::{Dir, Rom};
+
+    use super::*;
+
+    fn game(name: &str, source: &str, roms: &[&str]) -> Node {
+        Node::Game(Game {
+            name: String::from(name),
+            description: None,
+            roms: roms
+                .iter()
+                .map(|rom| Rom {
+                    element: BytesStart::from_content(format!(r#"rom name="{rom}" size="1""#), 3)
+                        .into_owned(),
+                })
+                .collect(),
+            source: String::from(source),
+        })
+    }
+
+    fn nodes() -> Vec<Node> {
+        vec![Node::Dir(Dir {
+            name: String::from("artwork"),
+            children: vec![
+                game("005", "artwork.dat", &["a.png"]),
+                game("1942", "artwork.dat", &["b.png", "B.png"]),
+                game("005", "extra.dat", &["c.png"]),
+            ],
+        })]
+    }
+
+    fn names(nodes: &[Node]) -> Vec<String> {
+        let Node::Dir(dir) = &nodes[0] else {
+            panic!("expected dir");
+        };
+        dir.children
+            .iter()
+            .map(|node| match node {
+                Node::Game(game) => format!(
+                    "{}:{}",
+                    game.name,
+                    game.roms
+                        .iter()
+                        .map(Rom::name)
+                        .collect::<Vec<_>>()
+                        .join(",")
+                ),
+                Node::Dir(dir) => dir.name.clone(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn it_reports_duplicates() {
+        let mut nodes = nodes();
+        let report = resolve_duplicates(&mut nodes, Duplicates::Warn)
+            .unwrap()
+            .report;
+        assert_eq!(
+            vec![
+                "duplicate game `005` in `artwork` (artwork.dat and extra.dat)",
+                "duplicate rom `B.png` in game `artwork/1942` (artwork.dat)",
+            ],
+            report
+        );
+        assert_eq!(
+            vec!["005:a.png", "1942:b.png,B.png", "005:c.png"],
+            names(&nodes)
+        );
+    }
+
+    #[test]
+    fn it_fails_on_duplicates() {
+        let mut nodes = nodes();
+        let result = resolve_duplicates(&mut nodes, Duplicates::Error);
+        assert!(result.is_err());
+        if let Err(err) = result {
+            assert!(err.to_string().starts_with("duplicates found:\n"));
+        }
+    }
+
+    #[test]
+    fn it_keeps_first_duplicate() {
+        let mut nodes = nodes();
+        let dropped = resolve_duplicates(&mut nodes, Duplicates::KeepFirst)
+            .unwrap()
+            .dropped;
+        assert_eq!(vec!["005:a.png", "1942:b.png"], names(&nodes));
+        assert_eq!(Counts { games: 1, roms: 2 }, dropped);
+    }
+
+    #[test]
+    fn it_keeps_last_duplicate() {
+        let mut nodes = nodes();
+        let dropped = resolve_duplicates(&mut nodes, Duplicates::KeepLast)
+            .unwrap()
+            .dropped;
+        assert_eq!(Counts { games: 1, roms: 2 }, dropped);
+        assert_eq!(vec!["1942:B.png", "005:c.png"], names(&nodes));
+    }
+
+    #[test]
+    fn it_renames_duplicates() {
+        let mut nodes = nodes();
+        let report = resolve_duplicates(&mut nodes, Duplicates::Rename)
+            .unwrap()
+            .report;
+        assert_eq!(
+            vec!["005:a.png", "1942:b.png,B (2).png", "005 (2):c.png"],
+            names(&nodes)
+        );
+        assert_eq!(
+            "duplicate game `005` in `artwork` (artwork.dat and extra.dat), renamed to `005 (2)`",
+            report[0]
+        );
+    }
+
+    #[test]
+    fn it_does_not_compare_dirs_and_games() {
+        let mut nodes = vec![
+            Node::Dir(Dir {
+                name: String::from("dats"),
+                children: Vec::new(),
+            }),
+            game("dats", "all_non-zipped_content.dat", &[]),
+        ];
+        let report = resolve_duplicates(&mut nodes, Duplicates::Error)
+            .unwrap()
+            .report;
+        assert!(report.is_empty());
+    }
+
+    #[test]
+    fn it_compares_unescaped_names() {
+        let mut nodes = vec![
+            game("a &amp; b", "artwork.dat", &[]),
+            game("A &#38; B", "samples.dat", &[]),
+        ];
+        let report = resolve_duplicates(&mut nodes, Duplicates::Warn)
+            .unwrap()
+            .report;
+        assert_eq!(
+            vec!["duplicate game `A & B` in `/` (artwork.dat and samples.dat)"],
+            report
+        );
+    }
+
+    #[test]
+    fn it_ignores_duplicates() {
+        let mut nodes = nodes();
+        let report = resolve_duplicates(&mut nodes, Duplicates::Ignore)
+            .unwrap()
+            .report;
+        assert!(report.is_empty());
+        assert_eq!(3, names(&nodes).len());
+    }
+}
@@ -11,6 +11,9 @@ pub const FILES: [&str; 3] = [ALL_NON_ZIPPED_CONTENT, ARTWORK, SAMPLES];
 
 use regex::RegexBuilder;
 
+/// Pattern of MAME Extras input file names, capturing MAME version.
+pub const EXTRAS_PATTERN: &str = r"(?:^|[\\/])MAME (?<version>\d\.\d+) EXTRAs\.zip$";
+
 /// Tries to extract MAME version from specified input file path.
 ///
 /// # Examples
@@ -31,14 +34,28 @@ use regex::RegexBuilder;
 /// ```
 #[must_use]
 pub fn extract_version(file_name: &str) -> Option<f32> {
-    let Ok(re) = RegexBuilder::new(r"MAME (?<version>\d\.\d+) EXTRAs\.zip$")
-        .case_insensitive(true)
-        .build()
-    else {
-        return None; // Should never happen
+    extract_version_matching(file_name, EXTRAS_PATTERN)
+}
+
+/// Tries to extract version from specified input file path, using specified pattern.
+/// Pattern is a case-insensitive regular expression with a `version` named group.
+///
+/// # Examples
+///
+/// ```
+/// use convert_mame_extras_romvault::files::extract_version_matching;
+///
+/// let pattern = r"(?:^|[\\/])HBMAME (?<version>\d\.\d+) EXTRAs\.zip$";
+/// assert_eq!(Some(0.245), extract_version_matching("HBMAME 0.245 EXTRAs.zip", pattern));
+/// assert_eq!(None, extract_version_matching("MAME 0.264 EXTRAs.zip", pattern));
+/// ```
+#[must_use]
+pub fn extract_version_matching(file_name: &str, pattern: &str) -> Option<f32> {
+    let Ok(re) = RegexBuilder::new(pattern).case_insensitive(true).build() else {
+        return None; // Should never happen with a checked pattern
     };
     let caps = re.captures(file_name)?;
-    let Ok(version) = caps["version"].parse::<f32>() else {
+    let Ok(version) = caps.name("version")?.as_str().parse::<f32>() else {
         return None;
     };
 
@@ -52,38 +69,38 @@ mod tests {
     #[test]
     fn it_extracts_version_0264() {
         let input_file_path = "MAME 0.264 EXTRAs.zip";
-        let version = extract_version(&input_file_path);
+        let version = extract_version(input_file_path);
         assert!(version.is_some());
-        assert_eq!(0.264, version.unwrap());
+        assert_eq!(Some(0.264), version);
     }
 
     #[test]
     fn it_extracts_version_0264_if_lowercase() {
         let input_file_path = "mame 0.264 extras.zip";
-        let version = extract_version(&input_file_path);
+        let version = extract_version(input_file_path);
         assert!(version.is_some());
-        assert_eq!(0.264, version.unwrap());
+        assert_eq!(Some(0.264), version);
     }
 
     #[test]
     fn it_extracts_version_10() {
         let input_file_path = "MAME 1.0 EXTRAs.zip";
-        let version = extract_version(&input_file_path);
+        let version = extract_version(input_file_path);
         assert!(version.is_some());
-        assert_eq!(1.0, version.unwrap());
+        assert_eq!(Some(1.0), version);
     }
 
     #[test]
     fn it_handles_file_without_version() {
         let input_file_path = "MAME EXTRAs.zip";
-        let version = extract_version(&input_file_path);
+        let version = extract_version(input_file_path);
         assert!(version.is_none());
     }
 
     #[test]
     fn it_handles_empty_file() {
         let input_file_path = "";
-        let version = extract_version(&input_file_path);
+        let version = extract_version(input_file_path);
         assert!(version.is_none());
     }
 }
@@ -0,0 +1,282 @@
+//! Include and exclude filters for games and roms.
+//!
+//! Patterns are globs by default (e.g. `artwork/*`), or regular expressions
+//! when prefixed with `re:` (e.g. `re:^folders/.*\.mp4$`). Globs are matched
+//! case-insensitively.
+//!
+//! Game patterns only target games: they are matched against the dir path of the
+//! game (e.g. `artwork`), its full path (e.g. `artwork/005`), its name and its description.
+//! Rom patterns only target roms, and are matched against the rom name, so that a rom
+//! pattern such as `*.mp4` never drops a game whose description happens to match.
+
+use anyhow::anyhow;
+use globset::{GlobBuilder, GlobMatcher};
+use regex::Regex;
+use serde::Serialize;
+use std::str::FromStr;
+
+use crate::tree::{display_name, Game};
+
+/// Custom result with any context error.
+type Result<T> = anyhow::Result<T>;
+
+/// Pattern used to include or exclude games and roms.
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    /// Glob pattern, matched case-insensitively.
+    Glob(GlobMatcher),
+    /// Regular expression.
+    Regex(Regex),
+}
+
+impl Pattern {
+    /// Check if specified value matches this pattern.
+    #[must_use]
+    pub fn is_match(&self, value: &str) -> bool {
+        match self {
+            Self::Glob(glob) => glob.is_match(value),
+            Self::Regex(regex) => regex.is_match(value),
+        }
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = anyhow::Error;
+
+    /// Parse a glob, or a regular expression prefixed with `re:`.
+    /// An optional `glob:` prefix is also accepted for globs.
+    fn from_str(pattern: &str) -> Result<Self> {
+        if let Some(regex) = pattern.strip_prefix("re:") {
+            return Regex::new(regex)
+                .map(Self::Regex)
+                .map_err(|err| anyhow!("invalid regular expression `{regex}`: {err}"));
+        }
+
+        let glob = pattern.strip_prefix("glob:").unwrap_or(pattern);
+        GlobBuilder::new(glob)
+            .case_insensitive(true)
+            .build()
+            .map(|glob| Self::Glob(glob.compile_matcher()))
+            .map_err(|err| anyhow!("invalid glob `{glob}`: {err}"))
+    }
+}
+
+/// Include and exclude patterns applied to games and roms.
+#[derive(Clone, Debug, Default)]
+pub struct Filters {
+    /// If not empty, only games matching one of these patterns are kept.
+    pub include: Vec<Pattern>,
+    /// Games matching one of these patterns are dropped.
+    pub exclude: Vec<Pattern>,
+    /// If not empty, only roms matching one of these patterns are kept.
+    pub include_roms: Vec<Pattern>,
+    /// Roms matching one of these patterns are dropped.
+    pub exclude_roms: Vec<Pattern>,
+}
+
+/// Number of items dropped by filters.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Filtered {
+    /// Number of games dropped
+    pub games: usize,
+    /// Number of roms dropped, including roms of dropped games
+    pub roms: usize,
+}
+
+impl Filtered {
+    /// Add specified counts to these counts.
+    pub const fn add(&mut self, other: Self) {
+        self.games += other.games;
+        self.roms += other.roms;
+    }
+}
+
+impl Filters {
+    /// Check if no pattern is defined.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.include.is_empty()
+            && self.exclude.is_empty()
+            && self.include_roms.is_empty()
+            && self.exclude_roms.is_empty()
+    }
+
+    /// Apply filters to specified game located in specified dir path (e.g. `artwork`).
+    /// Game patterns are applied to the game, then rom patterns to its roms. A game
+    /// is also dropped when rom patterns drop all its roms.
+    ///
+    /// Returns `None` if the game is dropped, along with the number of items dropped.
+    #[must_use]
+    pub fn apply(&self, mut game: Game, dir_path: &str) -> (Option<Game>, Filtered) {
+        if self.is_empty() {
+            return (Some(game), Filtered::default());
+        }
+
+        let name = display_name(&game.name);
+        let path = if dir_path.is_empty() {
+            name.clone()
+        } else {
+            format!("{dir_path}/{name}")
+        };
+        let description = game
+            .description
+            .as_deref()
+            .map(display_name)
+            .unwrap_or_default();
+        let targets = [dir_path, path.as_str(), name.as_str(), description.as_str()];
+        let game_matches = |patterns: &[Pattern]| {
+            patterns
+                .iter()
+                .any(|pattern| targets.iter().any(|target| pattern.is_match(target)))
+        };
+
+        let dropped = Filtered {
+            games: 1,
+            roms: game.roms.len(),
+        };
+        if game_matches(&self.exclude) || (!self.include.is_empty() && !game_matches(&self.include))
+        {
+            return (None, dropped);
+        }
+
+        let count = game.roms.len();
+        game.roms.retain(|rom| {
+            let rom_name = display_name(&rom.name());
+            let rom_matches =
+                |patterns: &[Pattern]| patterns.iter().any(|pattern| pattern.is_match(&rom_name));
+            !rom_matches(&self.exclude_roms)
+                && (self.include_roms.is_empty() || rom_matches(&self.include_roms))
+        });
+
+        if game.roms.is_empty() && count > 0 {
+            return (None, dropped);
+        }
+
+        let filtered = Filtered {
+            games: 0,
+            roms: count - game.roms.len(),
+        };
+        (Some(game), filtered)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use quick_xml::events::BytesStart;
+
+    use crate::tree::Rom;
+
+    use super::*;
+
+    fn game(name: &str, description: &str, roms: &[&str]) -> Game {
+        Game {
+            name: String::from(name),
+            description: Some(String::from(description)),
+            roms: roms
+                .iter()
+                .map(|rom| Rom {
+                    element: BytesStart::from_content(format!(r#"rom name="{rom}" size="1""#), 3)
+                        .into_owned(),
+                })
+                .collect(),
+            source: String::from("artwork.dat"),
+        }
+    }
+
+    fn patterns(patterns: &[&str]) -> Vec<Pattern> {
+        patterns.iter().map(|p| p.parse().unwrap()).collect()
+    }
+
+    fn filters(include: &[&str], exclude: &[&str]) -> Filters {
+        Filters {
+            include: patterns(include),
+            exclude: patterns(exclude),
+            ..Filters::default()
+        }
+    }
+
+    fn rom_filters(include: &[&str], exclude: &[&str]) -> Filters {
+        Filters {
+            include_roms: patterns(include),
+            exclude_roms: patterns(exclude),
+            ..Filters::default()
+        }
+    }
+
+    fn rom_names(game: &Game) -> Vec<String> {
+        game.roms.iter().map(Rom::name).collect()
+    }
+
+    #[test]
+    fn it_keeps_everything_without_patterns() {
+        let (game, filtered) = Filters::default().apply(game("005", "005", &["a.png"]), "artwork");
+        assert!(game.is_some());
+        assert_eq!(Filtered::default(), filtered);
+    }
+
+    #[test]
+    fn it_excludes_game_by_path() {
+        let filters = filters(&[], &["artwork/0*"]);
+        let (game, filtered) = filters.apply(game("005", "005", &["a.png", "b.png"]), "artwork");
+        assert!(game.is_none());
+        assert_eq!(Filtered { games: 1, roms: 2 }, filtered);
+    }
+
+    #[test]
+    fn it_excludes_game_by_description_regex() {
+        let filters = filters(&[], &["re:(?i)friends$"]);
+        let (game, _) = filters.apply(game("005", "005 &amp; Friends", &["a.png"]), "artwork");
+        assert!(game.is_none());
+    }
+
+    #[test]
+    fn it_excludes_roms() {
+        let filters = rom_filters(&[], &["*.MP4"]);
+        let (game, filtered) = filters.apply(
+            game("folders", "folders", &["Folders\\a.ini", "video\\b.mp4"]),
+            "folders",
+        );
+        assert_eq!(vec!["Folders\\a.ini"], rom_names(&game.unwrap()));
+        assert_eq!(Filtered { games: 0, roms: 1 }, filtered);
+    }
+
+    #[test]
+    fn it_includes_game_by_dir() {
+        let filters = filters(&["samples"], &[]);
+        let (circus, _) = filters.apply(game("circus", "circus", &["pop.wav"]), "samples");
+        assert!(circus.is_some());
+        let (artwork, _) = filters.apply(game("005", "005", &["a.png"]), "artwork");
+        assert!(artwork.is_none());
+    }
+
+    #[test]
+    fn it_includes_roms() {
+        let filters = rom_filters(&["*.lay"], &[]);
+        let (game, filtered) =
+            filters.apply(game("005", "005", &["default.lay", "a.png"]), "artwork");
+        assert_eq!(vec!["default.lay"], rom_names(&game.unwrap()));
+        assert_eq!(Filtered { games: 0, roms: 1 }, filtered);
+    }
+
+    #[test]
+    fn it_matches_rom_patterns_only_against_roms() {
+        let filters = rom_filters(&[], &["*.mp4"]);
+        let (game, filtered) = filters.apply(game("intro", "intro.mp4", &["a.png"]), "artwork");
+        assert_eq!(vec!["a.png"], rom_names(&game.unwrap()));
+        assert_eq!(Filtered::default(), filtered);
+    }
+
+    #[test]
+    fn it_matches_game_patterns_only_against_games() {
+        let filters = filters(&[], &["*.png"]);
+        let (game, filtered) = filters.apply(game("005", "005", &["a.png"]), "artwork");
+        assert_eq!(vec!["a.png"], rom_names(&game.unwrap()));
+        assert_eq!(Filtered::default(), filtered);
+    }
+
+    #[test]
+    fn it_rejects_invalid_patterns() {
+        assert!("re:(".parse::<Pattern>().is_err());
+        assert!("[a".parse::<Pattern>().is_err());
+    }
+}
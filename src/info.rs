@@ -0,0 +1,510 @@
+//! Summary of an input bundle, without converting it.
+//!
+//! For each source dat expected by the profile, the summary reports whether it is present,
+//! its header fields, and its number of machines, roms and declared bytes.
+//! Counts are also grouped by section (e.g. `artwork`).
+
+use anyhow::anyhow;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::reader::Reader;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::fmt::{self, Write};
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use crate::mapping::Source;
+use crate::profile::Profile;
+use crate::zip::{check_entries, open_input_file, InputAccess};
+
+/// Custom result with any context error.
+type Result<T> = anyhow::Result<T>;
+
+/// Number of items declared in a dat or a section.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Counts {
+    /// Number of machines
+    pub machines: usize,
+    /// Number of roms
+    pub roms: usize,
+    /// Sum of declared rom sizes, in bytes
+    pub bytes: u64,
+}
+
+impl Counts {
+    /// Add specified counts to these counts.
+    const fn add(&mut self, other: Self) {
+        self.machines += other.machines;
+        self.roms += other.roms;
+        self.bytes += other.bytes;
+    }
+}
+
+impl fmt::Display for Counts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} machine{}, {} rom{}, {} bytes",
+            self.machines,
+            plural(self.machines),
+            self.roms,
+            plural(self.roms),
+            self.bytes
+        )
+    }
+}
+
+/// Field of a dat header.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct HeaderField {
+    /// Field name (e.g. `version`)
+    pub name: String,
+    /// Unescaped field value
+    pub value: String,
+}
+
+/// Summary of a source dat.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct EntryInfo {
+    /// Entry name in input Zip file
+    pub name: String,
+    /// Whether entry is present in input Zip file
+    pub present: bool,
+    /// Header fields, in dat order
+    pub header: Vec<HeaderField>,
+    /// Items declared in dat
+    pub counts: Counts,
+}
+
+/// Summary of a section.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct SectionInfo {
+    /// Section name (e.g. `artwork`)
+    pub name: String,
+    /// Items declared in section
+    pub counts: Counts,
+}
+
+/// Summary of an input bundle.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct BundleInfo {
+    /// Input file name
+    pub file_name: String,
+    /// Version extracted from input file name
+    pub version: Option<f32>,
+    /// Versions declared in headers of source dats
+    pub header_versions: Vec<String>,
+    /// Whether all expected entries are present
+    pub complete: bool,
+    /// Expected entries which are missing
+    pub missing: Vec<String>,
+    /// Summary of each expected source dat
+    pub entries: Vec<EntryInfo>,
+    /// Summary of each section
+    pub sections: Vec<SectionInfo>,
+}
+
+/// Plural suffix for specified count.
+const fn plural(count: usize) -> &'static str {
+    if count == 1 {
+        ""
+    } else {
+        "s"
+    }
+}
+
+/// Read header fields and counts of specified source dat.
+///
+/// Machines placed in a dir of the source are counted in that section,
+/// other machines in the root dir of the source, if any.
+fn read_entry<R: BufRead>(
+    mut reader: Reader<R>,
+    source: &Source,
+    sections: &mut Vec<SectionInfo>,
+) -> Result<(Vec<HeaderField>, Counts)> {
+    let (header, first_machine) = read_header(&mut reader, &source.entry)?;
+    let mut counts = Counts::default();
+    let (mut section, mut machine) = match first_machine {
+        Some(e) => start_machine(&e, source)?,
+        None => (None, None),
+    };
+    let mut buf = Vec::new();
+
+    loop {
+        let event = reader.read_event_into(&mut buf).map_err(|err| {
+            anyhow!(
+                "{} at position {}: {err}",
+                source.entry,
+                reader.error_position()
+            )
+        })?;
+        match event {
+            Event::Start(e) if matches!(e.name().as_ref(), b"machine" | b"game") => {
+                (section, machine) = start_machine(&e, source)?;
+            }
+            Event::Empty(e) if machine.is_some() && e.name().as_ref() == b"rom" => {
+                let size = e
+                    .try_get_attribute("size")?
+                    .and_then(|size| String::from_utf8_lossy(&size.value).parse::<u64>().ok())
+                    .unwrap_or_default();
+                if let Some(machine) = machine.as_mut() {
+                    machine.roms += 1;
+                    machine.bytes += size;
+                }
+            }
+            Event::End(e) if matches!(e.name().as_ref(), b"machine" | b"game") => {
+                if let Some(machine) = machine.take() {
+                    counts.add(machine);
+                    if let Some(name) = section.take() {
+                        add_section(sections, &name, machine);
+                    }
+                }
+            }
+            Event::Eof => break,
+            _ => (),
+        }
+        buf.clear();
+    }
+
+    Ok((header, counts))
+}
+
+/// Section and initial counts of specified machine element of specified source dat.
+fn start_machine(
+    element: &BytesStart,
+    source: &Source,
+) -> Result<(Option<String>, Option<Counts>)> {
+    let name = element
+        .try_get_attribute("name")?
+        .map(|name| name.unescape_value().map(Cow::into_owned))
+        .transpose()?
+        .unwrap_or_default();
+    let section = if source.dirs.contains(&name) {
+        Some(name)
+    } else {
+        source.root_dir.clone()
+    };
+    let machine = Counts {
+        machines: 1,
+        ..Counts::default()
+    };
+
+    Ok((section, Some(machine)))
+}
+
+/// Read header fields of specified source dat, stopping at the end of its header.
+///
+/// Reading also stops at the first machine if the dat has no header: this machine is returned
+/// with the fields, so that the caller can keep reading the dat.
+///
+/// # Errors
+///
+/// Will return `Err` if header is not a valid XML document.
+pub fn read_header<R: BufRead>(
+    reader: &mut Reader<R>,
+    dat: &str,
+) -> Result<(Vec<HeaderField>, Option<BytesStart<'static>>)> {
+    let mut header = Vec::new();
+    let mut in_header = false;
+    let mut field: Option<HeaderField> = None;
+    let mut buf = Vec::new();
+
+    loop {
+        let event = reader
+            .read_event_into(&mut buf)
+            .map_err(|err| anyhow!("{dat} at position {}: {err}", reader.error_position()))?;
+        match event {
+            Event::Start(e) if e.name().as_ref() == b"header" => in_header = true,
+            Event::End(e) if e.name().as_ref() == b"header" => break,
+            Event::Start(e) if in_header => {
+                field = Some(HeaderField {
+                    name: String::from_utf8_lossy(e.name().as_ref()).into_owned(),
+                    value: String::new(),
+                });
+            }
+            Event::Empty(e) if in_header => header.push(HeaderField {
+                name: String::from_utf8_lossy(e.name().as_ref()).into_owned(),
+                value: String::new(),
+            }),
+            Event::Text(e) if in_header => {
+                if let Some(field) = field.as_mut() {
+                    field.value.push_str(&e.unescape()?);
+                }
+            }
+            Event::End(_) if in_header => header.extend(field.take()),
+            Event::Start(e) if matches!(e.name().as_ref(), b"machine" | b"game") => {
+                return Ok((header, Some(e.into_owned())));
+            }
+            Event::Eof => break,
+            _ => (),
+        }
+        buf.clear();
+    }
+
+    Ok((header, None))
+}
+
+/// Add specified counts to section with specified name, creating it if needed.
+fn add_section(sections: &mut Vec<SectionInfo>, name: &str, counts: Counts) {
+    if let Some(section) = sections.iter_mut().find(|section| section.name == name) {
+        section.counts.add(counts);
+    } else {
+        sections.push(SectionInfo {
+            name: String::from(name),
+            counts,
+        });
+    }
+}
+
+/// Summarize specified input bundle according to specified profile.
+///
+/// # Errors
+///
+/// Will return `Err` if input file is not a valid Zip file, or if a source dat is not a valid XML document.
+pub fn bundle_info(input_file: &Path, profile: &Profile) -> Result<BundleInfo> {
+    let mut zip = open_input_file(input_file, &[], InputAccess::Read)?;
+    let required_entries = profile.mapping.required_entries(None);
+    let complete = check_entries(&zip, &required_entries).is_ok();
+
+    let file_name = input_file
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut entries = Vec::new();
+    let mut sections = Vec::new();
+    for source in &profile.mapping.sources {
+        let Ok(entry) = zip.by_name(&source.entry) else {
+            entries.push(EntryInfo {
+                name: source.entry.clone(),
+                present: false,
+                header: Vec::new(),
+                counts: Counts::default(),
+            });
+            continue;
+        };
+        let reader = Reader::from_reader(BufReader::new(entry));
+        let (header, counts) = read_entry(reader, source, &mut sections)?;
+        entries.push(EntryInfo {
+            name: source.entry.clone(),
+            present: true,
+            header,
+            counts,
+        });
+    }
+
+    let mut header_versions: Vec<String> = entries
+        .iter()
+        .flat_map(|entry| &entry.header)
+        .filter(|field| field.name == "version")
+        .map(|field| field.value.clone())
+        .collect();
+    header_versions.sort();
+    header_versions.dedup();
+
+    Ok(BundleInfo {
+        version: profile.extract_version(&file_name),
+        file_name,
+        header_versions,
+        complete,
+        missing: entries
+            .iter()
+            .filter(|entry| !entry.present)
+            .map(|entry| entry.name.clone())
+            .collect(),
+        entries,
+        sections,
+    })
+}
+
+impl fmt::Display for BundleInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let version = |version: Option<String>| version.unwrap_or_else(|| String::from("unknown"));
+        writeln!(f, "File: {}", self.file_name)?;
+        writeln!(
+            f,
+            "Version: {} (file name), {} (headers)",
+            version(self.version.map(|version| version.to_string())),
+            version((!self.header_versions.is_empty()).then(|| self.header_versions.join(", ")))
+        )?;
+        if self.complete {
+            writeln!(f, "Status: complete")?;
+        } else {
+            writeln!(f, "Status: incomplete, missing {}", self.missing.join(", "))?;
+        }
+
+        writeln!(f, "Entries:")?;
+        let entry_width = self
+            .entries
+            .iter()
+            .map(|entry| entry.name.len())
+            .max()
+            .unwrap_or_default();
+        for entry in &self.entries {
+            if entry.present {
+                writeln!(f, "  {:entry_width$}  {}", entry.name, entry.counts)?;
+            } else {
+                writeln!(f, "  {:entry_width$}  missing", entry.name)?;
+            }
+        }
+
+        writeln!(f, "Sections:")?;
+        let width = self
+            .sections
+            .iter()
+            .map(|section| section.name.len())
+            .max()
+            .unwrap_or_default();
+        for section in &self.sections {
+            writeln!(f, "  {:width$}  {}", section.name, section.counts)?;
+        }
+
+        writeln!(f, "Headers:")?;
+        for entry in self.entries.iter().filter(|entry| entry.present) {
+            let mut fields = String::new();
+            for field in &entry.header {
+                let separator = if fields.is_empty() { "" } else { ", " };
+                write!(fields, "{separator}{}: {}", field.name, field.value)?;
+            }
+            writeln!(f, "  {:entry_width$}  {fields}", entry.name)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAT: &str = r#"<?xml version="1.0"?>
+<datafile>
+	<header>
+		<name>MAME 0.264 EXTRAs (all non-zipped content)</name>
+		<version>0.264</version>
+		<author>Pleasuredome &amp; co</author>
+	</header>
+	<machine name="dats">
+		<description>dats</description>
+		<rom name="history.xml" size="100"/>
+		<rom name="mameinfo.dat" size="200"/>
+	</machine>
+	<machine name="folders">
+		<description>folders</description>
+		<rom name="Folders\category.ini" size="300"/>
+	</machine>
+	<machine name="other">
+		<rom name="other.txt" size="bad"/>
+	</machine>
+</datafile>
+"#;
+
+    #[test]
+    fn it_reads_entry() {
+        let source = Source {
+            entry: String::from("all_non-zipped_content.dat"),
+            root_dir: None,
+            dirs: vec![String::from("dats"), String::from("folders")],
+        };
+        let mut sections = Vec::new();
+        let (header, counts) = read_entry(Reader::from_str(DAT), &source, &mut sections).unwrap();
+        let fields: Vec<String> = header
+            .iter()
+            .map(|field| format!("{}={}", field.name, field.value))
+            .collect();
+        assert_eq!(
+            vec![
+                "name=MAME 0.264 EXTRAs (all non-zipped content)",
+                "version=0.264",
+                "author=Pleasuredome & co",
+            ],
+            fields
+        );
+        assert_eq!(
+            Counts {
+                machines: 3,
+                roms: 4,
+                bytes: 600
+            },
+            counts
+        );
+        assert_eq!(
+            vec![
+                SectionInfo {
+                    name: String::from("dats"),
+                    counts: Counts {
+                        machines: 1,
+                        roms: 2,
+                        bytes: 300
+                    }
+                },
+                SectionInfo {
+                    name: String::from("folders"),
+                    counts: Counts {
+                        machines: 1,
+                        roms: 1,
+                        bytes: 300
+                    }
+                },
+            ],
+            sections
+        );
+    }
+
+    #[test]
+    fn it_reads_header_only() {
+        let (header, _) =
+            read_header(&mut Reader::from_str(DAT), "all_non-zipped_content.dat").unwrap();
+        assert_eq!(3, header.len());
+        assert_eq!("0.264", header[1].value);
+        let (header, machine) = read_header(
+            &mut Reader::from_str(
+                "<datafile><machine name=\"a\"><description>a</description></machine></datafile>",
+            ),
+            "a.dat",
+        )
+        .unwrap();
+        assert!(header.is_empty());
+        assert!(machine.is_some());
+    }
+
+    #[test]
+    fn it_reads_empty_header_fields() {
+        let dat = "<datafile>\n\t<header>\n\t\t<name>a</name>\n\t\t<author></author>\n\t\t<url/>\n\t\t<comment> </comment>\n\t</header>\n</datafile>";
+        let (header, _) = read_header(&mut Reader::from_str(dat), "a.dat").unwrap();
+        let fields: Vec<String> = header
+            .iter()
+            .map(|field| format!("{}={}", field.name, field.value))
+            .collect();
+        assert_eq!(vec!["name=a", "author=", "url=", "comment= "], fields);
+    }
+
+    #[test]
+    fn it_counts_machines_of_dat_without_header() {
+        let source = Source {
+            entry: String::from("a.dat"),
+            root_dir: Some(String::from("a")),
+            dirs: Vec::new(),
+        };
+        let mut sections = Vec::new();
+        let dat = r#"<datafile><machine name="a"><rom name="a" size="1"/></machine><machine name="b"/></datafile>"#;
+        let (header, counts) = read_entry(Reader::from_str(dat), &source, &mut sections).unwrap();
+        assert!(header.is_empty());
+        assert_eq!(1, counts.machines);
+        assert_eq!(1, counts.roms);
+    }
+
+    #[test]
+    fn it_counts_root_dir_section() {
+        let source = Source {
+            entry: String::from("artwork.dat"),
+            root_dir: Some(String::from("artwork")),
+            dirs: Vec::new(),
+        };
+        let mut sections = Vec::new();
+        read_entry(Reader::from_str(DAT), &source, &mut sections).unwrap();
+        assert_eq!(1, sections.len());
+        assert_eq!("artwork", sections[0].name);
+        assert_eq!(3, sections[0].counts.machines);
+    }
+}
@@ -3,63 +3,574 @@
 //! This crate can be used to convert MAME Extras Zip file
 //! to a compatible format to use with Romvault.
 
+pub mod batch;
+pub mod check;
+pub mod checksum;
+pub mod compress;
 pub mod dat;
+pub mod date;
+pub mod diff;
+pub mod dtd;
+pub mod duplicates;
 pub mod files;
+pub mod filter;
+pub mod info;
+pub mod logger;
+pub mod mapping;
+pub mod merge;
+pub mod paths;
+mod pool;
+pub mod profile;
+pub mod progress;
+pub mod provenance;
+pub mod report;
+pub mod rom;
+pub mod sort;
+pub mod tree;
+pub mod watch;
 pub mod zip;
 
-use crate::{dat::generate_output, zip::check_input_file};
-use clap::Parser;
-use files::extract_version;
-use std::{path::PathBuf, time::Instant};
+use crate::{
+    batch::{check_collisions, convert_all, find_inputs, output_file_name, print_table},
+    checksum::{Checksum, Manifest},
+    compress::{read_dat, Compression},
+    dat::{generate_content, generate_output, Indent, IndentChar},
+    date::DateSource,
+    diff::{diff_dats, diff_games, read_games, Change},
+    dtd::{validate_file, Doctype},
+    duplicates::Duplicates,
+    filter::{Filters, Pattern},
+    info::bundle_info,
+    logger::LogFormat,
+    mapping::Mapping,
+    merge::{merge_dats, HeaderRule, MergeConfig},
+    profile::{Profile, MAME_EXTRAS},
+    progress::{progress_bars, progress_logs, ProgressCallback},
+    report::Report,
+    rom::RomCheck,
+    watch::{watch, WatchConfig},
+    zip::{open_input_file, InputAccess, SharedArchive},
+};
+use clap::{ArgAction, CommandFactory, Parser, Subcommand};
+use log::{error, info, warn};
+use quick_xml::reader::Reader;
+use std::{
+    env,
+    ffi::OsString,
+    fs,
+    io::{self, IsTerminal},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    thread,
+    time::{Duration, Instant},
+};
 
 /// Main configuration to hold various parameters:
 /// - Input file from command line arguments
 /// - Output file from command line arguments or generated from input file name
+/// - Profile of input bundle (naming pattern, headers and mapping of source dats to output dirs)
 /// - Version computed from input file name
+/// - Optional validation of rom entries
+/// - Policy for duplicate games and roms
+/// - Include and exclude filters
+/// - Optional selection of sections to convert
+/// - Split of rom paths into nested dirs
+/// - Optional canonical ordering of dirs, games and roms
+/// - Optional indentation of output
+/// - Document type declaration
+/// - Compression of output file
+/// - Checksum sidecar files written next to output file
+/// - Optional provenance recorded in header comment
+/// - Optional date written to header
 pub struct Config {
     /// Zip file used for input.
     input_file_path: PathBuf,
     /// Generated dat will be written into this output file.
     output_file_path: PathBuf,
+    /// Profile of input bundle, with mapping of source dats to output dirs built-in or read from a TOML file.
+    profile: Profile,
     /// Version extracted from input Zip file name. Will be used for dat generation.
     version: Option<f32>,
+    /// Optional validation of rom entries, with behaviour on malformed entries.
+    rom_check: Option<RomCheck>,
+    /// Policy applied to duplicate games and roms.
+    duplicates: Duplicates,
+    /// Include and exclude filters applied to games and roms.
+    filters: Filters,
+    /// Sections to convert (e.g. dats, folders, artwork, samples). All sections are converted if `None`.
+    sections: Option<Vec<String>>,
+    /// Split rom paths containing directories into nested dirs and games.
+    split_paths: bool,
+    /// Sort dirs, games and roms by name instead of keeping input order.
+    sort: bool,
+    /// Optional indentation of output. Output is written without indentation if `None`.
+    indent: Option<Indent>,
+    /// Document type declaration, referencing the Logiqx DTD or embedding a DTD declaring dirs.
+    doctype: Doctype,
+    /// Compression of output file.
+    compression: Compression,
+    /// Checksum sidecar files written next to output file.
+    checksums: Vec<Checksum>,
+    /// Record input file, source dat headers and converter version in header comment.
+    provenance: bool,
+    /// Source of date written to header, if selected. Defaults to the input date, if any.
+    date: Option<DateSource>,
+    /// Optional callback receiving progress of each source dat.
+    progress: Option<ProgressCallback>,
+    /// Access to input file content: read in memory, or memory-mapped.
+    input_access: InputAccess,
+    /// Input Zip file, opened once and shared by section workers.
+    archive: OnceLock<SharedArchive>,
+    /// Number of jobs parsing machines of each source dat, and writing the output.
+    jobs: usize,
 }
 
 impl Config {
-    /// Build configuration according to specified command line arguments
-    fn build(args: &Args) -> Self {
-        let input_file_name = args.input_file.file_name().unwrap();
-        let version = extract_version(input_file_name.to_str().unwrap());
+    /// Create configuration converting specified input file with specified profile and default options.
+    ///
+    /// Version is extracted from input file name. If output file is not specified,
+    /// the input file name is used, with a .dat extension. Output is compressed according
+    /// to output file extension (see [`Compression::from_path`]).
+    #[must_use]
+    pub fn new(input_file: &Path, output_file: Option<&Path>, profile: Profile) -> Self {
+        let input_file_name = input_file.file_name().unwrap_or_default();
+        let version = profile.extract_version(&input_file_name.to_string_lossy());
 
-        let input_file_path = PathBuf::from(&args.input_file);
         let mut output_file_path: PathBuf;
 
-        if args.output_file.is_none() {
+        if let Some(output_file) = output_file {
+            output_file_path = PathBuf::from(output_file);
+        } else {
             // Compute output file name from input file name
             output_file_path = PathBuf::from(input_file_name);
             output_file_path.set_extension("dat");
-        } else {
-            output_file_path = PathBuf::from(args.output_file.as_ref().unwrap());
         }
 
         Self {
-            input_file_path,
+            input_file_path: PathBuf::from(input_file),
+            compression: Compression::from_path(&output_file_path),
             output_file_path,
+            profile,
             version,
+            rom_check: None,
+            duplicates: Duplicates::default(),
+            filters: Filters::default(),
+            sections: None,
+            split_paths: false,
+            sort: false,
+            indent: None,
+            doctype: Doctype::default(),
+            checksums: Vec::new(),
+            provenance: false,
+            date: None,
+            progress: None,
+            input_access: InputAccess::Read,
+            archive: OnceLock::new(),
+            jobs: 1,
         }
     }
+
+    /// Parse machines of each source dat, and write the output, with specified number of jobs.
+    ///
+    /// Each source dat is converted by a single job by default.
+    #[must_use]
+    pub const fn with_jobs(mut self, jobs: NonZeroUsize) -> Self {
+        self.jobs = jobs.get();
+        self
+    }
+
+    /// Input Zip file, checked to contain the entries required by selected sections.
+    ///
+    /// The file is opened on first call, then shared by later calls.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if input file is not accessible, is not a valid Zip file,
+    /// or does not contain required entries (see [`zip::check_input_file`]).
+    pub fn input_archive(&self) -> anyhow::Result<SharedArchive> {
+        if let Some(archive) = self.archive.get() {
+            return Ok(archive.clone());
+        }
+        let required_entries = self
+            .profile
+            .mapping
+            .required_entries(self.sections.as_deref());
+        let archive = open_input_file(&self.input_file_path, &required_entries, self.input_access)?;
+
+        Ok(self.archive.get_or_init(|| archive).clone())
+    }
+
+    /// Send progress of each source dat to specified callback during conversion.
+    #[must_use]
+    pub fn with_progress(mut self, callback: ProgressCallback) -> Self {
+        self.progress = Some(callback);
+        self
+    }
+
+    /// Build configuration according to specified input file, optional output file and conversion options
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if profile or mapping file is invalid, or if an unknown section is selected.
+    fn build(
+        input_file: &Path,
+        output_file: Option<&Path>,
+        options: &Options,
+    ) -> anyhow::Result<Self> {
+        let profile = options.profile()?;
+        let mut config = Self::new(input_file, output_file, profile);
+        if let Some(compression) = options.compress {
+            config.compression = compression;
+            if output_file.is_none() {
+                config.output_file_path = compression.append_extension(&config.output_file_path);
+            }
+        }
+
+        Ok(Self {
+            rom_check: options.check_roms,
+            duplicates: options.duplicates,
+            filters: Filters {
+                include: options.include.clone(),
+                exclude: options.exclude.clone(),
+                include_roms: options.include_rom.clone(),
+                exclude_roms: options.exclude_rom.clone(),
+            },
+            sections: options.sections.clone(),
+            split_paths: options.split_paths,
+            sort: options.sort,
+            indent: options.indent.map(|width| Indent {
+                char: options.indent_char,
+                width,
+            }),
+            doctype: options.doctype,
+            checksums: options.checksums.clone(),
+            provenance: options.provenance,
+            date: options.date,
+            input_access: options.input_access,
+            ..config
+        })
+    }
 }
 
 /// Convert MAME Extras to Romvault format.
 #[derive(Parser)]
+#[command(
+    arg_required_else_help = true,
+    after_help = "If no command is specified, `convert` is used: convert-mame-extras-romvault [OPTIONS] <INPUT_FILE> [OUTPUT_FILE]"
+)]
+struct Cli {
+    /// Command to run.
+    #[command(subcommand)]
+    command: Command,
+    /// Only print warnings and errors.
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Print debug messages of each thread (-v), and a trace of each converted machine (-vv).
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    verbose: u8,
+    /// Format of log messages.
+    #[arg(long, value_name = "FORMAT", global = true, default_value = "text")]
+    log_format: LogFormat,
+}
+
+/// Available commands.
+#[derive(Subcommand)]
+enum Command {
+    /// Convert an Extras Zip file to a Romvault dat (default command).
+    Convert(Args),
+    /// Convert an Extras Zip file and check that the result matches an existing dat.
+    Verify(VerifyArgs),
+    /// List games added, removed or changed between two dats.
+    Diff(DiffArgs),
+    /// Check that a dat is valid against its inline DTD, or the DTD of converted dats.
+    Validate(ValidateArgs),
+    /// Summarize an Extras Zip file without converting it.
+    Info(InfoArgs),
+    /// Merge a converted Extras dat into a MAME dat, as an additional dir.
+    Merge(MergeArgs),
+    /// Convert every Zip file matching profile naming pattern in a directory.
+    Batch(BatchArgs),
+    /// Convert Zip files matching profile naming pattern as they appear in a directory.
+    Watch(WatchArgs),
+}
+
+/// Arguments of verify command.
+#[derive(clap::Args)]
+struct VerifyArgs {
+    /// Input Zip file containing MAME Extras dats, or dats expected by selected profile.
+    input_file: PathBuf,
+    /// Existing dat expected to match the conversion of input file.
+    dat_file: PathBuf,
+    /// Conversion options, which must match the options used to generate the existing dat.
+    #[command(flatten)]
+    options: Options,
+}
+
+/// Arguments of diff command.
+#[derive(clap::Args)]
+struct DiffArgs {
+    /// Old dat.
+    old_file: PathBuf,
+    /// New dat.
+    new_file: PathBuf,
+}
+
+/// Arguments of validate command.
+#[derive(clap::Args)]
+struct ValidateArgs {
+    /// Dat to validate.
+    file: PathBuf,
+}
+
+/// Arguments of info command.
+#[derive(clap::Args)]
+struct InfoArgs {
+    /// Input Zip file containing MAME Extras dats, or dats expected by selected profile.
+    input_file: PathBuf,
+    /// Print summary as JSON.
+    #[arg(long)]
+    json: bool,
+    /// Profile of input bundle.
+    #[command(flatten)]
+    profile: ProfileOptions,
+}
+
+/// Arguments of merge command.
+#[derive(clap::Args)]
+struct MergeArgs {
+    /// MAME dat (Logiqx format) receiving Extras content.
+    mame_file: PathBuf,
+    /// Extras dat converted to Romvault format.
+    extras_file: PathBuf,
+    /// Output file containing merged dat.
+    output_file: PathBuf,
+    /// Dir containing Extras content in merged dat.
+    #[arg(long, value_name = "NAME", default_value = "extras")]
+    dir: String,
+    /// Headers of merged dat: MAME headers, Extras headers, or MAME headers with Extras name and description appended.
+    #[arg(long, value_name = "RULE", default_value = "combined")]
+    header: HeaderRule,
+}
+
+/// Arguments of batch command.
+#[derive(clap::Args)]
+struct BatchArgs {
+    /// Directory containing Zip files to convert. Only files matching the naming pattern of selected profile are converted.
+    input_dir: PathBuf,
+    /// Directory receiving generated dats, named from profile name and version (e.g. `Extras 0.264.dat`).
+    output_dir: PathBuf,
+    /// Scan subdirectories of input directory.
+    #[arg(short, long)]
+    recursive: bool,
+    /// Number of files converted in parallel. Defaults to the number of available CPUs.
+    #[arg(short, long, value_name = "N")]
+    jobs: Option<NonZeroUsize>,
+    /// Conversion options applied to each file.
+    #[command(flatten)]
+    options: Options,
+}
+
+/// Arguments of watch command.
+#[derive(clap::Args)]
+struct WatchArgs {
+    /// Directory receiving new Zip files.
+    inbox: PathBuf,
+    /// Directory receiving generated dats, named from profile name and version (e.g. `Extras 0.264.dat`).
+    output_dir: PathBuf,
+    /// Move converted Zip files to this directory.
+    #[arg(long, value_name = "DIR")]
+    processed: Option<PathBuf>,
+    /// Delay between two checks of new files, in seconds. A file is converted when its size did not change between two checks.
+    #[arg(long, value_name = "SECONDS", default_value_t = 2)]
+    interval: u64,
+    /// Poll directory instead of using native file system notifications.
+    #[arg(long)]
+    poll: bool,
+    /// Delay after which a file whose size stopped changing, but which is still not a valid Zip file with expected dats, is given up, in seconds.
+    #[arg(long, value_name = "SECONDS", default_value_t = 600)]
+    timeout: u64,
+    /// Conversion options applied to each file.
+    #[command(flatten)]
+    options: Options,
+}
+
+/// Arguments of conversion.
+#[derive(clap::Args)]
 struct Args {
-    /// Input Zip file containing MAME Extras dats (all_non-zipped_content.dat, artwork.dat and samples.dat).
+    /// Input Zip file containing MAME Extras dats (all_non-zipped_content.dat, artwork.dat and samples.dat), or dats expected by selected profile.
     input_file: PathBuf,
     /// Optional output file compatible with Romvault. If not specified, the input Zip file name is used, with a .dat extension.
     output_file: Option<PathBuf>,
+    /// Number of jobs parsing machines of each source dat and writing the output. Defaults to 1, streaming each source dat.
+    #[arg(short, long, value_name = "N")]
+    jobs: Option<NonZeroUsize>,
+    /// Write a JSON report of the conversion (counts, warnings, timings, output SHA-256) to FILE.
+    #[arg(long, value_name = "FILE")]
+    report: Option<PathBuf>,
+    /// Write a JSON manifest (input file name and SHA-256, converter version, detected version, output SHA-256) to FILE.
+    #[arg(long, value_name = "FILE")]
+    manifest: Option<PathBuf>,
+    /// Conversion options.
+    #[command(flatten)]
+    options: Options,
+}
+
+/// Options of conversion.
+#[derive(clap::Args)]
+struct Options {
+    /// Check rom entries (name, size, crc, sha1 and md5) and normalize hashes to lowercase.
+    /// Malformed entries are either reported as warnings or stop the conversion.
+    #[arg(long, value_name = "MODE")]
+    check_roms: Option<RomCheck>,
+    /// Policy applied when game names are not unique within a dir, or rom names are not unique within a game.
+    /// Detection walks the whole generated tree, unless `ignore` is specified.
+    #[arg(long, value_name = "POLICY", default_value = "warn")]
+    duplicates: Duplicates,
+    /// Only keep games matching this pattern (glob, or regex prefixed with `re:`).
+    /// Matched against dir path, game path, game name and description. Can be repeated.
+    #[arg(long, value_name = "PATTERN")]
+    include: Vec<Pattern>,
+    /// Drop games matching this pattern (glob, or regex prefixed with `re:`). Can be repeated.
+    #[arg(long, value_name = "PATTERN")]
+    exclude: Vec<Pattern>,
+    /// Only keep roms whose name matches this pattern (glob, or regex prefixed with `re:`). Can be repeated.
+    #[arg(long, value_name = "PATTERN")]
+    include_rom: Vec<Pattern>,
+    /// Drop roms whose name matches this pattern (glob, or regex prefixed with `re:`). Can be repeated.
+    #[arg(long, value_name = "PATTERN")]
+    exclude_rom: Vec<Pattern>,
+    /// Comma-separated list of sections to convert (dats, folders, artwork, samples with built-in mapping). All sections are converted by default.
+    #[arg(long, value_name = "SECTIONS", value_delimiter = ',')]
+    sections: Option<Vec<String>>,
+    /// Profile of input bundle.
+    #[command(flatten)]
+    profile: ProfileOptions,
+    /// Split rom names containing directories (separated by `\` or `/`) into nested dirs and games.
+    #[arg(long)]
+    split_paths: bool,
+    /// Sort dirs, games and roms by name (case-insensitive, then exact), so that the output does not depend on input order.
+    #[arg(long)]
+    sort: bool,
+    /// Write each element on its own line, indented by WIDTH characters per level. Output is compact by default.
+    #[arg(long, value_name = "WIDTH")]
+    indent: Option<usize>,
+    /// Character used to indent output.
+    #[arg(
+        long,
+        value_name = "CHAR",
+        default_value = "space",
+        requires = "indent"
+    )]
+    indent_char: IndentChar,
+    /// Document type declaration: reference to the Logiqx DTD, or inline DTD declaring dirs.
+    #[arg(long, value_name = "DOCTYPE", default_value = "logiqx")]
+    doctype: Doctype,
+    /// Compression of output file: Zip file containing the dat, gzip or Zstandard. Detected from output file extension (.zip, .gz, .zst) by default.
+    #[arg(long, value_name = "FORMAT")]
+    compress: Option<Compression>,
+    /// Comma-separated list of checksum files written next to output file (e.g. `Extras.dat.sha256`).
+    #[arg(long, value_name = "KINDS", value_delimiter = ',')]
+    checksums: Vec<Checksum>,
+    /// Record input file name, size and SHA-1, headers of source dats and converter version in the header comment.
+    #[arg(long)]
+    provenance: bool,
+    /// Source of date header: latest modification time of source dats in input file (default), current time,
+    /// or no date. `SOURCE_DATE_EPOCH` replaces the date of every source when set.
+    #[arg(long, value_name = "SOURCE")]
+    date: Option<DateSource>,
+    /// Access to input file: read in memory once, or memory-mapped. With `mmap`, the process is killed (SIGBUS) if the
+    /// file is truncated or becomes unreadable while converted, e.g. on network storage.
+    #[arg(long, value_name = "ACCESS", default_value = "read")]
+    input_access: InputAccess,
+}
+
+/// Selection of input bundle profile.
+#[derive(clap::Args)]
+struct ProfileOptions {
+    /// Profile of input bundle: built-in `mame-extras`, `hbmame-extras`, `mame-multimedia` or `mame-sl-chds`, or a TOML file describing another bundle.
+    #[arg(long, value_name = "NAME|FILE", default_value = MAME_EXTRAS)]
+    profile: String,
+    /// TOML file mapping source dats to output dirs, replacing profile mapping.
+    #[arg(long, value_name = "FILE")]
+    mapping: Option<PathBuf>,
+}
+
+impl ProfileOptions {
+    /// Load selected profile, replacing its mapping if a mapping file is specified.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if profile or mapping file is invalid.
+    fn load(&self) -> anyhow::Result<Profile> {
+        let mut profile = Profile::load(&self.profile)?;
+        if let Some(mapping_file) = &self.mapping {
+            profile.mapping = Mapping::from_file(mapping_file)?;
+        }
+
+        Ok(profile)
+    }
 }
 
-/// Parse arguments, build application configuration, and tries to generate output dat file.
+impl Options {
+    /// Load selected profile, and check selected sections.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if profile or mapping file is invalid, or if an unknown section is selected.
+    fn profile(&self) -> anyhow::Result<Profile> {
+        let profile = self.profile.load()?;
+        if let Some(sections) = &self.sections {
+            profile.mapping.check_sections(sections)?;
+        }
+
+        Ok(profile)
+    }
+
+    /// Compression selected for output files named by the converter (e.g. in batch mode).
+    fn output_compression(&self) -> Compression {
+        self.compress.unwrap_or_default()
+    }
+}
+
+/// Insert default `convert` command in specified arguments, unless a command or help is requested.
+///
+/// Global logging options (e.g. `-v`) may precede the command.
+fn with_default_command(args: Vec<OsString>) -> Vec<OsString> {
+    let command = Cli::command();
+    let mut index = 1;
+    while let Some(arg) = args.get(index).and_then(|arg| arg.to_str()) {
+        if arg == "--log-format" {
+            index += 2;
+        } else if arg == "--quiet"
+            || arg == "--verbose"
+            || arg.starts_with("--log-format=")
+            || (arg.len() > 1
+                && arg.starts_with('-')
+                && arg[1..].chars().all(|c| c == 'q' || c == 'v'))
+        {
+            index += 1;
+        } else {
+            break;
+        }
+    }
+    let Some(first) = args.get(index).and_then(|arg| arg.to_str()) else {
+        return args;
+    };
+    let is_command = first == "help"
+        || command
+            .get_subcommands()
+            .any(|subcommand| subcommand.get_name() == first);
+    if is_command || first == "-h" || first == "--help" {
+        return args;
+    }
+
+    let mut args = args;
+    args.insert(index, OsString::from("convert"));
+    args
+}
+
+/// Parse arguments, and run selected command.
 ///
 /// Returns 0 if no error occurred.
 /// Returns 1 in case of error.
@@ -68,29 +579,419 @@ pub fn real_main() -> i8 {
     let now = Instant::now();
 
     // Parse arguments
-    let args = Args::parse();
-
-    if let Err(err) = check_input_file(args.input_file.as_path()) {
+    let cli = Cli::parse_from(with_default_command(env::args_os().collect()));
+    if let Err(err) = logger::init(logger::level(cli.quiet, cli.verbose), cli.log_format) {
         eprintln!("Error: {err}");
         return 1;
     }
 
+    match cli.command {
+        Command::Convert(args) => {
+            let progress_bars =
+                cli.log_format == LogFormat::Text && !cli.quiet && io::stderr().is_terminal();
+            run_convert(&args, progress_bars, now)
+        }
+        Command::Verify(args) => run_verify(&args, now),
+        Command::Diff(args) => run_diff(&args),
+        Command::Validate(args) => run_validate(&args),
+        Command::Info(args) => run_info(&args),
+        Command::Merge(args) => run_merge(&args, now),
+        Command::Batch(args) => run_batch(&args, now),
+        Command::Watch(args) => run_watch(&args),
+    }
+}
+
+/// Build configuration according to specified arguments, and tries to generate output dat file.
+///
+/// Progress is displayed with progress bars if requested, or logged periodically otherwise.
+///
+/// Returns 0 if no error occurred.
+/// Returns 1 in case of error.
+fn run_convert(args: &Args, progress_bars: bool, now: Instant) -> i8 {
     // Build configuration
-    let config = Config::build(&args);
-    println!(
+    let config = match Config::build(&args.input_file, args.output_file.as_deref(), &args.options) {
+        Ok(config) => config
+            .with_jobs(args.jobs.unwrap_or(NonZeroUsize::MIN))
+            .with_progress(if progress_bars {
+                self::progress_bars()
+            } else {
+                progress_logs()
+            }),
+        Err(err) => {
+            error!("{err}");
+            return 1;
+        }
+    };
+
+    if let Err(err) = config.input_archive() {
+        error!("{err}");
+        return 1;
+    }
+
+    info!(
         "Generating {} for version {}",
         config.output_file_path.display(),
         config.version.unwrap_or_default(),
     );
 
     // Generate output dat file
-    if let Err(err) = generate_output(&config) {
-        eprintln!("Error: {err}");
+    let summary = match generate_output(&config) {
+        Ok(summary) => {
+            for warning in &summary.warnings {
+                warn!("{warning}");
+            }
+            if !config.filters.is_empty() {
+                info!(
+                    "Filtered: {} games, {} roms",
+                    summary.filtered.games, summary.filtered.roms
+                );
+            }
+            summary
+        }
+        Err(err) => {
+            error!("{err}");
+            return 1;
+        }
+    };
+
+    if let Some(manifest_path) = &args.manifest {
+        let manifest = Manifest::new(&config);
+        if let Err(err) = manifest.and_then(|manifest| manifest.write(manifest_path)) {
+            error!("{err}");
+            return 1;
+        }
+    }
+
+    let elapsed = now.elapsed();
+    if let Some(report_path) = &args.report {
+        let report = Report::new(&config, summary, elapsed);
+        if let Err(err) = report.and_then(|report| report.write(report_path)) {
+            error!("{err}");
+            return 1;
+        }
+    }
+    info!("Elapsed: {elapsed:.2?}");
+
+    0
+}
+
+/// Merge Extras dat into MAME dat according to specified arguments.
+///
+/// Returns 0 if no error occurred.
+/// Returns 1 in case of error.
+fn run_merge(args: &MergeArgs, now: Instant) -> i8 {
+    let config = MergeConfig {
+        mame_file: args.mame_file.clone(),
+        extras_file: args.extras_file.clone(),
+        output_file: args.output_file.clone(),
+        dir: args.dir.clone(),
+        header: args.header,
+    };
+
+    info!(
+        "Merging {} into {} as {}",
+        config.extras_file.display(),
+        config.mame_file.display(),
+        config.output_file.display(),
+    );
+
+    match merge_dats(&config) {
+        Ok(summary) => {
+            for warning in summary.warnings {
+                warn!("{warning}");
+            }
+            info!(
+                "Merged: {} MAME entries, {} Extras entries in `{}`",
+                summary.mame_entries, summary.extras_entries, config.dir
+            );
+        }
+        Err(err) => {
+            error!("{err}");
+            return 1;
+        }
+    }
+
+    let elapsed = now.elapsed();
+    info!("Elapsed: {elapsed:.2?}");
+
+    0
+}
+
+/// Convert every matching Zip file of a directory according to specified arguments.
+///
+/// Returns 0 if all files were converted.
+/// Returns 1 in case of error.
+fn run_batch(args: &BatchArgs, now: Instant) -> i8 {
+    let profile = match args.options.profile() {
+        Ok(profile) => profile,
+        Err(err) => {
+            error!("{err}");
+            return 1;
+        }
+    };
+    let inputs = match find_inputs(&args.input_dir, args.recursive, &profile) {
+        Ok(inputs) => inputs,
+        Err(err) => {
+            error!("{err}");
+            return 1;
+        }
+    };
+    if inputs.is_empty() {
+        error!(
+            "no file matching `{}` found in {}",
+            profile.pattern,
+            args.input_dir.display()
+        );
+        return 1;
+    }
+    if let Err(err) = fs::create_dir_all(&args.output_dir) {
+        error!("cannot create {}: {err}", args.output_dir.display());
+        return 1;
+    }
+
+    let configs = inputs
+        .iter()
+        .map(|input| {
+            let output = args
+                .options
+                .output_compression()
+                .append_extension(&args.output_dir.join(output_file_name(
+                    &profile,
+                    profile.extract_version(&input.file_name),
+                )));
+            Config::build(&input.path, Some(&output), &args.options)
+        })
+        .collect::<anyhow::Result<Vec<_>>>();
+    let configs = match configs.and_then(|configs| check_collisions(&configs).map(|()| configs)) {
+        Ok(configs) => configs,
+        Err(err) => {
+            error!("{err}");
+            return 1;
+        }
+    };
+
+    let jobs = args
+        .jobs
+        .or_else(|| thread::available_parallelism().ok())
+        .map_or(1, NonZeroUsize::get);
+    info!(
+        "Converting {} file{} from {} with {jobs} job{}",
+        configs.len(),
+        if configs.len() > 1 { "s" } else { "" },
+        args.input_dir.display(),
+        if jobs > 1 { "s" } else { "" },
+    );
+
+    let results = convert_all(&configs, jobs);
+    print_table(&configs, &results);
+
+    let elapsed = now.elapsed();
+    info!("Elapsed: {elapsed:.2?}");
+
+    i8::from(results.iter().any(Result::is_err))
+}
+
+/// Watch a directory and convert new matching Zip files according to specified arguments.
+///
+/// Returns 1 if directory cannot be watched.
+fn run_watch(args: &WatchArgs) -> i8 {
+    let profile = match args.options.profile() {
+        Ok(profile) => profile,
+        Err(err) => {
+            error!("{err}");
+            return 1;
+        }
+    };
+    for dir in [Some(&args.output_dir), args.processed.as_ref()]
+        .into_iter()
+        .flatten()
+    {
+        if let Err(err) = fs::create_dir_all(dir) {
+            error!("cannot create {}: {err}", dir.display());
+            return 1;
+        }
+    }
+
+    let config = WatchConfig {
+        inbox: args.inbox.clone(),
+        processed_dir: args.processed.clone(),
+        interval: Duration::from_secs(args.interval.max(1)),
+        poll: args.poll,
+        timeout: Duration::from_secs(args.timeout),
+    };
+    let result = watch(&config, &profile, |input| {
+        let version = input
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| profile.extract_version(name));
+        let output = args
+            .options
+            .output_compression()
+            .append_extension(&args.output_dir.join(output_file_name(&profile, version)));
+        Config::build(input, Some(&output), &args.options)
+    });
+    if let Err(err) = result {
+        error!("{err}");
+        return 1;
+    }
+
+    0
+}
+
+/// Convert input file in memory according to specified arguments, and compare result with existing dat.
+///
+/// Returns 0 if generated dat matches existing dat.
+/// Returns 1 in case of mismatch or error.
+fn run_verify(args: &VerifyArgs, now: Instant) -> i8 {
+    let config = match Config::build(&args.input_file, Some(&args.dat_file), &args.options) {
+        Ok(config) => config,
+        Err(err) => {
+            error!("{err}");
+            return 1;
+        }
+    };
+
+    if let Err(err) = config.input_archive() {
+        error!("{err}");
         return 1;
     }
 
+    info!(
+        "Verifying {} against {}",
+        args.dat_file.display(),
+        args.input_file.display()
+    );
+
+    let generated = match generate_content(&config) {
+        Ok((content, _)) => content,
+        Err(err) => {
+            error!("{err}");
+            return 1;
+        }
+    };
+    let existing = match read_dat(&args.dat_file) {
+        Ok(existing) => existing,
+        Err(err) => {
+            error!("{err}");
+            return 1;
+        }
+    };
+
+    let code = if generated == existing {
+        info!("{} is up to date", args.dat_file.display());
+        0
+    } else {
+        info!("{} does not match conversion", args.dat_file.display());
+        let dat_file = args.dat_file.display().to_string();
+        let changes =
+            read_games(Reader::from_reader(existing.as_slice()), &dat_file).and_then(|old| {
+                let new = read_games(Reader::from_reader(generated.as_slice()), "conversion")?;
+                Ok(diff_games(&old, &new))
+            });
+        match changes {
+            Ok(changes) if changes.is_empty() => {
+                println!("Games are identical, headers or order differ");
+            }
+            Ok(changes) => {
+                for change in changes {
+                    println!("{change}");
+                }
+            }
+            Err(err) => error!("{err}"),
+        }
+        1
+    };
+
     let elapsed = now.elapsed();
-    println!("Elapsed: {elapsed:.2?}");
+    info!("Elapsed: {elapsed:.2?}");
+
+    code
+}
+
+/// Print games added, removed or changed between two dats.
+///
+/// Returns 0 if games are identical.
+/// Returns 1 if games differ, or in case of error.
+fn run_diff(args: &DiffArgs) -> i8 {
+    let changes = match diff_dats(&args.old_file, &args.new_file) {
+        Ok(changes) => changes,
+        Err(err) => {
+            error!("{err}");
+            return 1;
+        }
+    };
+
+    for change in &changes {
+        println!("{change}");
+    }
+    let count = |f: fn(&Change) -> bool| changes.iter().filter(|change| f(change)).count();
+    println!(
+        "{} added, {} removed, {} changed",
+        count(|change| matches!(change, Change::Added(_))),
+        count(|change| matches!(change, Change::Removed(_))),
+        count(|change| matches!(change, Change::Changed { .. })),
+    );
+
+    i8::from(!changes.is_empty())
+}
+
+/// Print validity errors of a dat.
+///
+/// Returns 0 if dat is valid.
+/// Returns 1 if dat is invalid, or in case of error.
+fn run_validate(args: &ValidateArgs) -> i8 {
+    let errors = match validate_file(&args.file) {
+        Ok(errors) => errors,
+        Err(err) => {
+            error!("{err}");
+            return 1;
+        }
+    };
+
+    for error in &errors {
+        println!("{error}");
+    }
+    if errors.is_empty() {
+        println!("{} is valid", args.file.display());
+    } else {
+        println!(
+            "{} error{}",
+            errors.len(),
+            if errors.len() > 1 { "s" } else { "" }
+        );
+    }
+
+    i8::from(!errors.is_empty())
+}
+
+/// Print summary of input file according to specified arguments.
+///
+/// Returns 0 if no error occurred.
+/// Returns 1 in case of error.
+fn run_info(args: &InfoArgs) -> i8 {
+    let info = args
+        .profile
+        .load()
+        .and_then(|profile| bundle_info(&args.input_file, &profile));
+    let info = match info {
+        Ok(info) => info,
+        Err(err) => {
+            error!("{err}");
+            return 1;
+        }
+    };
+
+    if args.json {
+        match serde_json::to_string_pretty(&info) {
+            Ok(json) => println!("{json}"),
+            Err(err) => {
+                error!("{err}");
+                return 1;
+            }
+        }
+    } else {
+        print!("{info}");
+    }
 
     0
 }
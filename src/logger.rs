@@ -0,0 +1,192 @@
+//! Diagnostics of commands, with a configurable verbosity and format.
+//!
+//! In text format, informational messages are written to standard output, errors and
+//! warnings to standard error with an `Error:` or `Warning:` prefix. Debug and trace
+//! messages are written to standard error, with the name of the thread which emitted them
+//! (e.g. `artwork.dat` while a source dat is converted).
+//! In JSON format, every message is written to standard error as a JSON object on a single line.
+
+use clap::ValueEnum;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde::Serialize;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{io, thread};
+
+/// Custom result with any context error.
+type Result<T> = anyhow::Result<T>;
+
+/// Format of log messages.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Human readable messages.
+    #[default]
+    Text,
+    /// One JSON object per message, for log aggregation.
+    Json,
+}
+
+/// Log message in JSON format.
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    /// Milliseconds since Unix epoch
+    timestamp_ms: u128,
+    /// Level (e.g. `WARN`)
+    level: Level,
+    /// Name of thread which emitted message
+    thread: &'a str,
+    /// Module which emitted message
+    target: &'a str,
+    /// Message
+    message: String,
+}
+
+/// Logger writing messages to standard output and standard error.
+pub struct Logger {
+    /// Most verbose level written
+    level: LevelFilter,
+    /// Format of messages
+    format: LogFormat,
+}
+
+impl Logger {
+    /// Create logger writing messages up to specified level, in specified format.
+    #[must_use]
+    pub const fn new(level: LevelFilter, format: LogFormat) -> Self {
+        Self { level, format }
+    }
+
+    /// Format specified record, emitted by thread with specified name.
+    #[must_use]
+    pub fn format(&self, record: &Record, thread: &str) -> String {
+        match self.format {
+            LogFormat::Text => match record.level() {
+                Level::Error => format!("Error: {}", record.args()),
+                Level::Warn => format!("Warning: {}", record.args()),
+                Level::Info => record.args().to_string(),
+                level => format!("{level} [{thread}] {}", record.args()),
+            },
+            LogFormat::Json => {
+                let json_record = JsonRecord {
+                    timestamp_ms: SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_millis(),
+                    level: record.level(),
+                    thread,
+                    target: record.target(),
+                    message: record.args().to_string(),
+                };
+                serde_json::to_string(&json_record).unwrap_or_default()
+            }
+        }
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let current = thread::current();
+        let line = self.format(record, current.name().unwrap_or("unnamed"));
+        // Messages which cannot be written (e.g. closed pipe) are dropped
+        if self.format == LogFormat::Text && record.level() == Level::Info {
+            let _ = writeln!(io::stdout().lock(), "{line}");
+        } else {
+            let _ = writeln!(io::stderr().lock(), "{line}");
+        }
+    }
+
+    fn flush(&self) {
+        let _ = io::stdout().flush();
+        let _ = io::stderr().flush();
+    }
+}
+
+/// Level of messages written for specified verbosity options.
+///
+/// # Examples
+///
+/// ```
+/// use convert_mame_extras_romvault::logger::level;
+/// use log::LevelFilter;
+///
+/// assert_eq!(LevelFilter::Warn, level(true, 0));
+/// assert_eq!(LevelFilter::Info, level(false, 0));
+/// assert_eq!(LevelFilter::Debug, level(false, 1));
+/// assert_eq!(LevelFilter::Trace, level(false, 2));
+/// ```
+#[must_use]
+pub const fn level(quiet: bool, verbose: u8) -> LevelFilter {
+    match (quiet, verbose) {
+        (true, _) => LevelFilter::Warn,
+        (false, 0) => LevelFilter::Info,
+        (false, 1) => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+/// Install logger writing messages up to specified level, in specified format.
+///
+/// # Errors
+///
+/// Will return `Err` if a logger is already installed.
+pub fn init(level: LevelFilter, format: LogFormat) -> Result<()> {
+    log::set_boxed_logger(Box::new(Logger::new(level, format)))?;
+    log::set_max_level(level);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(logger: &Logger, level: Level, message: &str) -> String {
+        logger.format(
+            &Record::builder()
+                .level(level)
+                .target("convert_mame_extras_romvault::dat")
+                .args(format_args!("{message}"))
+                .build(),
+            "artwork.dat",
+        )
+    }
+
+    #[test]
+    fn it_formats_text() {
+        let logger = Logger::new(LevelFilter::Trace, LogFormat::Text);
+        assert_eq!("Error: failed", format(&logger, Level::Error, "failed"));
+        assert_eq!("Warning: dup", format(&logger, Level::Warn, "dup"));
+        assert_eq!("Elapsed", format(&logger, Level::Info, "Elapsed"));
+        assert_eq!(
+            "DEBUG [artwork.dat] 2 games",
+            format(&logger, Level::Debug, "2 games")
+        );
+    }
+
+    #[test]
+    fn it_formats_json() {
+        let logger = Logger::new(LevelFilter::Trace, LogFormat::Json);
+        let line = format(&logger, Level::Warn, "a \"quoted\" name");
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!("WARN", value["level"]);
+        assert_eq!("artwork.dat", value["thread"]);
+        assert_eq!("convert_mame_extras_romvault::dat", value["target"]);
+        assert_eq!("a \"quoted\" name", value["message"]);
+        assert!(value["timestamp_ms"].is_u64());
+    }
+
+    #[test]
+    fn it_filters_levels() {
+        let logger = Logger::new(LevelFilter::Warn, LogFormat::Text);
+        let metadata = |level| Metadata::builder().level(level).build();
+        assert!(logger.enabled(&metadata(Level::Error)));
+        assert!(!logger.enabled(&metadata(Level::Info)));
+    }
+}
@@ -0,0 +1,291 @@
+//! Mapping of source dats to their placement in generated output.
+//!
+//! The built-in mapping matches MAME Extras bundles. It can be replaced by a TOML file, e.g.:
+//!
+//! ```toml
+//! [[source]]
+//! entry = "all_non-zipped_content.dat"
+//! dirs = ["dats", "folders"]
+//!
+//! [[source]]
+//! entry = "artwork.dat"
+//! root_dir = "artwork"
+//!
+//! [[source]]
+//! entry = "samples.dat"
+//! root_dir = "samples"
+//! ```
+
+use anyhow::anyhow;
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+use crate::files::{ALL_NON_ZIPPED_CONTENT, ARTWORK, SAMPLES};
+
+/// Custom result with any context error.
+type Result<T> = anyhow::Result<T>;
+
+/// Placement of a source dat in generated output.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Source {
+    /// Entry name of the dat in input Zip file.
+    pub entry: String,
+    /// Optional dir containing all games of the dat.
+    #[serde(default)]
+    pub root_dir: Option<String>,
+    /// Machines which are placed in a dir with the same name.
+    #[serde(default)]
+    pub dirs: Vec<String>,
+}
+
+/// Mapping of all source dats, in output order.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Mapping {
+    /// Source dats.
+    #[serde(rename = "source")]
+    pub sources: Vec<Source>,
+}
+
+impl Default for Mapping {
+    /// Mapping for MAME Extras bundles.
+    fn default() -> Self {
+        Self {
+            sources: vec![
+                Source {
+                    entry: String::from(ALL_NON_ZIPPED_CONTENT),
+                    root_dir: None,
+                    dirs: vec![String::from("dats"), String::from("folders")],
+                },
+                Source {
+                    entry: String::from(ARTWORK),
+                    root_dir: Some(String::from("artwork")),
+                    dirs: Vec::new(),
+                },
+                Source {
+                    entry: String::from(SAMPLES),
+                    root_dir: Some(String::from("samples")),
+                    dirs: Vec::new(),
+                },
+            ],
+        }
+    }
+}
+
+impl Source {
+    /// Sections provided by this source: its root dir and its dirs.
+    pub fn sections(&self) -> impl Iterator<Item = &str> {
+        self.root_dir
+            .iter()
+            .chain(self.dirs.iter())
+            .map(String::as_str)
+    }
+
+    /// Check if specified machine belongs to specified sections. All machines are converted if no
+    /// section is specified, or if this source provides no section.
+    /// A machine listed in dirs belongs to the section of the same name, other machines belong to
+    /// the root dir. If this source has no root dir, other machines are converted whenever the
+    /// source is (see [`Mapping::selected_sources`]), so that selecting any of its dirs keeps them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use convert_mame_extras_romvault::mapping::Mapping;
+    ///
+    /// let mapping = Mapping::default();
+    /// let sections = [String::from("dats")];
+    /// assert!(mapping.sources[0].converts("dats", Some(&sections)));
+    /// assert!(!mapping.sources[0].converts("folders", Some(&sections)));
+    /// assert!(mapping.sources[0].converts("ctrlr", Some(&sections)));
+    /// assert!(!mapping.sources[1].converts("005", Some(&sections)));
+    /// assert!(mapping.sources[0].converts("ctrlr", None));
+    /// ```
+    #[must_use]
+    pub fn converts(&self, machine: &str, sections: Option<&[String]>) -> bool {
+        let Some(sections) = sections else {
+            return true;
+        };
+        if self.sections().next().is_none() {
+            return true;
+        }
+        let is_selected = |section: &str| sections.iter().any(|s| s == section);
+        if self.dirs.iter().any(|dir| dir == machine) {
+            is_selected(machine)
+        } else {
+            self.root_dir.as_deref().is_none_or(is_selected)
+        }
+    }
+}
+
+impl Mapping {
+    /// Parse mapping from specified TOML content.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if content is not a valid mapping, or if it does not declare any source.
+    pub fn parse(content: &str) -> Result<Self> {
+        let mapping: Self = toml::from_str(content)?;
+        if mapping.sources.is_empty() {
+            return Err(anyhow!("mapping must declare at least one source"));
+        }
+
+        Ok(mapping)
+    }
+
+    /// Read mapping from specified TOML file.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if file cannot be read or is not a valid mapping.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .map_err(|err| anyhow!("cannot read mapping `{}`: {err}", path.display()))?;
+        Self::parse(&content).map_err(|err| anyhow!("invalid mapping `{}`: {err}", path.display()))
+    }
+
+    /// All sections which can be selected.
+    pub fn sections(&self) -> impl Iterator<Item = &str> {
+        self.sources.iter().flat_map(Source::sections)
+    }
+
+    /// Check that all specified sections are declared by this mapping.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` on the first unknown section.
+    pub fn check_sections(&self, sections: &[String]) -> Result<()> {
+        if let Some(unknown) = sections
+            .iter()
+            .find(|section| !self.sections().any(|s| s == section.as_str()))
+        {
+            return Err(anyhow!(
+                "unknown section `{unknown}`, expected one of: {}",
+                self.sections().collect::<Vec<_>>().join(", ")
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Get sources to convert for specified sections. All sources are converted if no section is specified.
+    /// A source is converted if its root dir or one of its dirs is selected, or if it has neither.
+    #[must_use]
+    pub fn selected_sources(&self, sections: Option<&[String]>) -> Vec<&Source> {
+        self.sources
+            .iter()
+            .filter(|source| {
+                let mut provided = source.sections().peekable();
+                sections.is_none_or(|sections| {
+                    provided.peek().is_none()
+                        || provided.any(|section| sections.iter().any(|s| s == section))
+                })
+            })
+            .collect()
+    }
+
+    /// Get Zip entries required to convert specified sections.
+    /// All entries are required if no section is specified.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use convert_mame_extras_romvault::mapping::Mapping;
+    ///
+    /// let mapping = Mapping::default();
+    /// assert_eq!(vec!["artwork.dat"], mapping.required_entries(Some(&[String::from("artwork")])));
+    /// assert_eq!(3, mapping.required_entries(None).len());
+    /// ```
+    #[must_use]
+    pub fn required_entries(&self, sections: Option<&[String]>) -> Vec<&str> {
+        self.selected_sources(sections)
+            .into_iter()
+            .map(|source| source.entry.as_str())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Built-in mapping written as TOML.
+    const DEFAULT_MAPPING: &str = r#"
+        [[source]]
+        entry = "all_non-zipped_content.dat"
+        dirs = ["dats", "folders"]
+
+        [[source]]
+        entry = "artwork.dat"
+        root_dir = "artwork"
+
+        [[source]]
+        entry = "samples.dat"
+        root_dir = "samples"
+    "#;
+
+    #[test]
+    fn it_parses_mapping() {
+        let mapping = Mapping::parse(DEFAULT_MAPPING);
+        assert!(mapping.is_ok());
+        assert_eq!(Mapping::default(), mapping.unwrap());
+    }
+
+    #[test]
+    fn it_rejects_empty_mapping() {
+        let result = Mapping::parse("source = []");
+        assert!(result.is_err());
+        if let Err(err) = result {
+            assert_eq!("mapping must declare at least one source", err.to_string());
+        }
+    }
+
+    #[test]
+    fn it_rejects_unknown_fields() {
+        assert!(Mapping::parse("[[source]]\nentry = \"a.dat\"\nroot = \"a\"").is_err());
+    }
+
+    #[test]
+    fn it_lists_sections() {
+        let mapping = Mapping::default();
+        let sections: Vec<&str> = mapping.sections().collect();
+        assert_eq!(vec!["dats", "folders", "artwork", "samples"], sections);
+    }
+
+    #[test]
+    fn it_checks_sections() {
+        let mapping = Mapping::default();
+        assert!(mapping.check_sections(&[String::from("samples")]).is_ok());
+        let result = mapping.check_sections(&[String::from("snap")]);
+        assert!(result.is_err());
+        if let Err(err) = result {
+            assert_eq!(
+                "unknown section `snap`, expected one of: dats, folders, artwork, samples",
+                err.to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn it_requires_entries_of_selected_sections() {
+        let mapping = Mapping::default();
+        let sections = [String::from("samples"), String::from("folders")];
+        assert_eq!(
+            vec![ALL_NON_ZIPPED_CONTENT, SAMPLES],
+            mapping.required_entries(Some(&sections))
+        );
+    }
+
+    #[test]
+    fn it_always_converts_sources_without_sections() {
+        let mapping = Mapping::parse(
+            "[[source]]\nentry = \"a.dat\"\n[[source]]\nentry = \"b.dat\"\nroot_dir = \"b\"",
+        )
+        .unwrap();
+        assert_eq!(
+            vec!["a.dat"],
+            mapping.required_entries(Some(&[String::from("c")]))
+        );
+    }
+}
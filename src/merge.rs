@@ -0,0 +1,466 @@
+//! Merge of a converted Extras dat into a MAME dat.
+//!
+//! Games of the MAME dat are copied unchanged, and the content of the Extras dat
+//! is placed in a dir (e.g. `extras`), so a single dat describes both in Romvault.
+//! Both dats must be Logiqx datafiles. As the merged dat contains a `<dir>`,
+//! its doctype embeds a DTD declaring it, along with the `<machine>` elements of MAME dats.
+
+use anyhow::anyhow;
+use clap::ValueEnum;
+use quick_xml::events::{BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::reader::Reader;
+use quick_xml::Writer;
+use std::io::{BufRead, Cursor};
+use std::path::{Path, PathBuf};
+
+use crate::compress::{read_dat, Compression};
+use crate::dat::{add_declaration, add_doctype, write_to_file};
+use crate::dtd::Doctype;
+use crate::tree::display_name;
+
+/// Custom result with any context error.
+type Result<T> = anyhow::Result<T>;
+
+/// Headers written to merged dat.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum HeaderRule {
+    /// Keep headers of MAME dat.
+    Mame,
+    /// Use headers of Extras dat.
+    Extras,
+    /// Keep headers of MAME dat, with name and description of Extras dat appended.
+    #[default]
+    Combined,
+}
+
+/// Configuration of a merge.
+pub struct MergeConfig {
+    /// MAME dat receiving Extras content.
+    pub mame_file: PathBuf,
+    /// Converted Extras dat.
+    pub extras_file: PathBuf,
+    /// Merged dat will be written into this output file.
+    pub output_file: PathBuf,
+    /// Dir containing Extras content in merged dat.
+    pub dir: String,
+    /// Headers written to merged dat.
+    pub header: HeaderRule,
+}
+
+/// Summary of a merge.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct MergeSummary {
+    /// Warnings raised during merge
+    pub warnings: Vec<String>,
+    /// Number of top-level games and dirs copied from MAME dat
+    pub mame_entries: usize,
+    /// Number of top-level games and dirs copied from Extras dat
+    pub extras_entries: usize,
+}
+
+/// Reader of datafile content, skipping declaration, doctype and indentation.
+///
+/// Whitespace-only text is indentation unless it is the whole content of an element
+/// (e.g. `<description> </description>`), in which case it is kept.
+struct DatReader<R> {
+    /// XML reader
+    reader: Reader<R>,
+    /// Dat name used in messages
+    source: String,
+    /// Buffer used by reader
+    buf: Vec<u8>,
+    /// Depth of current element, `datafile` being at depth 1
+    depth: usize,
+    /// Event read while looking for header, or end event following kept whitespace
+    pending: Option<Event<'static>>,
+    /// Whether the last returned event started an element
+    after_start: bool,
+    /// Whitespace-only text read right after a start event, kept if the element ends next
+    whitespace: Option<Event<'static>>,
+}
+
+impl<R: BufRead> DatReader<R> {
+    /// Build reader of specified dat.
+    fn new(reader: Reader<R>, source: &str) -> Self {
+        Self {
+            reader,
+            source: String::from(source),
+            buf: Vec::new(),
+            depth: 0,
+            pending: None,
+            after_start: false,
+            whitespace: None,
+        }
+    }
+
+    /// Read next event located in `datafile` element, or `None` at the end of the dat.
+    fn next(&mut self) -> Result<Option<Event<'static>>> {
+        if let Some(event) = self.pending.take() {
+            return Ok(Some(event));
+        }
+
+        loop {
+            let after_start = std::mem::take(&mut self.after_start);
+            let whitespace = self.whitespace.take();
+            self.buf.clear();
+            let event = match self.reader.read_event_into(&mut self.buf) {
+                Ok(event) => event.into_owned(),
+                Err(err) => {
+                    return Err(anyhow!(
+                        "{} at position {}: {err}",
+                        self.source,
+                        self.reader.error_position()
+                    ))
+                }
+            };
+            match event {
+                Event::Eof if self.depth > 0 => {
+                    return Err(anyhow!("{}: unexpected end of file", self.source))
+                }
+                Event::Eof => return Ok(None),
+                Event::Start(ref e) if self.depth == 0 && e.name().as_ref() != b"datafile" => {
+                    return Err(anyhow!("{} is not a Logiqx datafile", self.source))
+                }
+                Event::Start(_) => {
+                    self.depth += 1;
+                    if self.depth > 1 {
+                        self.after_start = true;
+                        return Ok(Some(event));
+                    }
+                }
+                Event::End(_) => {
+                    self.depth -= 1;
+                    if self.depth > 0 {
+                        if whitespace.is_some() {
+                            self.pending = Some(event);
+                            return Ok(whitespace);
+                        }
+                        return Ok(Some(event));
+                    }
+                }
+                Event::Text(ref e) if after_start && e.iter().all(u8::is_ascii_whitespace) => {
+                    self.whitespace = Some(event);
+                }
+                Event::Text(ref e) if e.iter().all(u8::is_ascii_whitespace) => (),
+                Event::Decl(_) | Event::DocType(_) | Event::PI(_) => (),
+                _ if self.depth > 0 => return Ok(Some(event)),
+                _ => (),
+            }
+        }
+    }
+
+    /// Read events of `header` element. Returns no event if dat has no header.
+    fn read_header(&mut self) -> Result<Vec<Event<'static>>> {
+        let mut header = Vec::new();
+        match self.next()? {
+            Some(Event::Start(e)) if e.name().as_ref() == b"header" => loop {
+                match self.next()? {
+                    Some(Event::End(_)) if self.depth == 1 => break,
+                    Some(event) => header.push(event),
+                    None => break,
+                }
+            },
+            Some(Event::Empty(e)) if e.name().as_ref() == b"header" => (),
+            event => self.pending = event,
+        }
+
+        Ok(header)
+    }
+
+    /// Copy remaining content to writer.
+    ///
+    /// Returns the names of top-level games and dirs.
+    fn copy_content(&mut self, writer: &mut Writer<Cursor<Vec<u8>>>) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        while let Some(event) = self.next()? {
+            match &event {
+                Event::Start(e) if self.depth == 2 => names.push(entry_name(e)?),
+                Event::Empty(e) if self.depth == 1 => names.push(entry_name(e)?),
+                _ => (),
+            }
+            writer.write_event(event)?;
+        }
+
+        Ok(names)
+    }
+}
+
+/// Name of specified top-level element, unescaped.
+fn entry_name(element: &BytesStart) -> Result<String> {
+    Ok(element
+        .try_get_attribute("name")?
+        .map(|name| display_name(&String::from_utf8_lossy(&name.value)))
+        .unwrap_or_default())
+}
+
+/// Get escaped text of specified header field.
+fn header_field(header: &[Event], field: &str) -> Option<String> {
+    header.windows(2).find_map(|events| match events {
+        [Event::Start(start), Event::Text(text)] if start.name().as_ref() == field.as_bytes() => {
+            Some(String::from_utf8_lossy(text).into_owned())
+        }
+        _ => None,
+    })
+}
+
+/// Replace escaped text of specified header field, if present.
+fn set_header_field(header: &mut [Event<'static>], field: &str, value: &str) {
+    for index in 1..header.len() {
+        if matches!(&header[index - 1], Event::Start(start) if start.name().as_ref() == field.as_bytes())
+            && matches!(header[index], Event::Text(_))
+        {
+            header[index] = Event::Text(BytesText::from_escaped(value.to_string()));
+        }
+    }
+}
+
+/// Build headers of merged dat according to specified rule.
+fn merge_headers(
+    mame: Vec<Event<'static>>,
+    extras: Vec<Event<'static>>,
+    rule: HeaderRule,
+) -> Vec<Event<'static>> {
+    match rule {
+        HeaderRule::Mame => mame,
+        HeaderRule::Extras => extras,
+        HeaderRule::Combined => {
+            let mut header = mame;
+            for field in ["name", "description"] {
+                if let (Some(mame_value), Some(extras_value)) =
+                    (header_field(&header, field), header_field(&extras, field))
+                {
+                    set_header_field(
+                        &mut header,
+                        field,
+                        &format!("{mame_value} + {extras_value}"),
+                    );
+                }
+            }
+            header
+        }
+    }
+}
+
+/// Merge content of Extras dat into MAME dat.
+///
+/// Returns the merged dat and a summary of the merge.
+/// As all Extras content is placed in the configured dir, only top-level games and dirs
+/// of MAME dat can collide with it: nested paths are not checked.
+///
+/// # Errors
+///
+/// Will return `Err` if a dat is not a valid Logiqx datafile,
+/// or if configured dir collides with a top-level game or dir of MAME dat.
+pub fn merge<M: BufRead, E: BufRead>(
+    mame: Reader<M>,
+    extras: Reader<E>,
+    config: &MergeConfig,
+) -> Result<(Writer<Cursor<Vec<u8>>>, MergeSummary)> {
+    let mame_source = config.mame_file.display().to_string();
+    let extras_source = config.extras_file.display().to_string();
+    let mut mame = DatReader::new(mame, &mame_source);
+    let mut extras = DatReader::new(extras, &extras_source);
+    let mut summary = MergeSummary::default();
+
+    let mame_header = mame.read_header()?;
+    let extras_header = extras.read_header()?;
+    let version = |header: &[Event]| {
+        header_field(header, "version")
+            .and_then(|version| version.split_whitespace().next().map(String::from))
+    };
+    if let (Some(mame_version), Some(extras_version)) =
+        (version(&mame_header), version(&extras_header))
+    {
+        if mame_version != extras_version {
+            summary.warnings.push(format!(
+                "version mismatch: {mame_source} is {mame_version}, {extras_source} is {extras_version}"
+            ));
+        }
+    }
+
+    let mut writer = Writer::new(Cursor::new(Vec::new()));
+    add_declaration(&mut writer)?;
+    add_doctype(&mut writer, Doctype::Inline)?;
+    writer.write_event(Event::Start(BytesStart::new("datafile")))?;
+
+    let header = merge_headers(mame_header, extras_header, config.header);
+    if !header.is_empty() {
+        writer.write_event(Event::Start(BytesStart::new("header")))?;
+        for event in header {
+            writer.write_event(event)?;
+        }
+        writer.write_event(Event::End(BytesEnd::new("header")))?;
+    }
+
+    let mame_names = mame.copy_content(&mut writer)?;
+    if let Some(name) = mame_names
+        .iter()
+        .find(|name| name.to_lowercase() == config.dir.to_lowercase())
+    {
+        return Err(anyhow!(
+            "`{name}` already exists in {mame_source}, choose another dir for Extras content"
+        ));
+    }
+    summary.mame_entries = mame_names.len();
+
+    let mut dir = BytesStart::new("dir");
+    dir.push_attribute(("name", config.dir.as_str()));
+    writer.write_event(Event::Start(dir))?;
+    summary.extras_entries = extras.copy_content(&mut writer)?.len();
+    writer.write_event(Event::End(BytesEnd::new("dir")))?;
+
+    writer.write_event(Event::End(BytesEnd::new("datafile")))?;
+
+    Ok((writer, summary))
+}
+
+/// Merge Extras dat into MAME dat, and write result to configured output file.
+///
+/// # Errors
+///
+/// Will return `Err` if a dat cannot be read or merged, or if output file cannot be written.
+pub fn merge_dats(config: &MergeConfig) -> Result<MergeSummary> {
+    let mame = read_dat(&config.mame_file)?;
+    let extras = read_dat(&config.extras_file)?;
+    let (writer, summary) = merge(
+        Reader::from_reader(mame.as_slice()),
+        Reader::from_reader(extras.as_slice()),
+        config,
+    )?;
+    let entry_name =
+        Path::new(config.output_file.file_stem().unwrap_or_default()).with_extension("dat");
+    write_to_file(
+        &writer.into_inner().into_inner(),
+        &config.output_file,
+        Compression::from_path(&config.output_file),
+        &entry_name.to_string_lossy(),
+    )?;
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dtd::validate;
+
+    use super::*;
+
+    const MAME: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE datafile PUBLIC "-//Logiqx//DTD ROM Management Datafile//EN" "http://www.logiqx.com/Dats/datafile.dtd">
+<datafile>
+	<header>
+		<name>MAME</name>
+		<description>MAME 0.264 ROMs (merged)</description>
+		<version>0.264</version>
+	</header>
+	<machine name="005">
+		<description>005 &amp; co</description>
+		<rom name="1346b.cpu-u25" size="2048" crc="8e68533e"/>
+	</machine>
+</datafile>
+"#;
+
+    const EXTRAS: &str = r#"<?xml version="1.0" encoding="UTF-8"?><!DOCTYPE datafile PUBLIC "-//Logiqx//DTD ROM Management Datafile//EN" "http://www.logiqx.com/Dats/datafile.dtd"><datafile><header><name>Extras</name><description>MAME 0.264 Extras (all content)</description><version>0.264</version></header><dir name="artwork"><game name="005"><description>005</description><rom name="005.png" size="1"/></game></dir></datafile>"#;
+
+    fn config(dir: &str, header: HeaderRule) -> MergeConfig {
+        MergeConfig {
+            mame_file: PathBuf::from("mame.dat"),
+            extras_file: PathBuf::from("extras.dat"),
+            output_file: PathBuf::from("merged.dat"),
+            dir: String::from(dir),
+            header,
+        }
+    }
+
+    fn merged(mame: &str, extras: &str, config: &MergeConfig) -> Result<(String, MergeSummary)> {
+        let (writer, summary) = merge(Reader::from_str(mame), Reader::from_str(extras), config)?;
+        Ok((
+            String::from_utf8(writer.into_inner().into_inner())?,
+            summary,
+        ))
+    }
+
+    #[test]
+    fn it_merges_extras_into_dir() {
+        let (output, summary) = merged(MAME, EXTRAS, &config("extras", HeaderRule::Mame)).unwrap();
+        assert!(output.ends_with(
+            r#"<datafile><header><name>MAME</name><description>MAME 0.264 ROMs (merged)</description><version>0.264</version></header><machine name="005"><description>005 &amp; co</description><rom name="1346b.cpu-u25" size="2048" crc="8e68533e"/></machine><dir name="extras"><dir name="artwork"><game name="005"><description>005</description><rom name="005.png" size="1"/></game></dir></dir></datafile>"#
+        ));
+        assert_eq!(
+            MergeSummary {
+                warnings: Vec::new(),
+                mame_entries: 1,
+                extras_entries: 1
+            },
+            summary
+        );
+    }
+
+    #[test]
+    fn it_declares_dir_in_doctype() {
+        let (output, _) = merged(MAME, EXTRAS, &config("extras", HeaderRule::Mame)).unwrap();
+        assert!(output.contains(&Doctype::Inline.declaration()));
+    }
+
+    #[test]
+    fn it_writes_valid_dat() {
+        let mame = MAME.replace(
+            "</version>",
+            "</version>\n\t\t<clrmamepro forcenodump=\"required\"/>",
+        );
+        let (output, _) = merged(&mame, EXTRAS, &config("extras", HeaderRule::Combined)).unwrap();
+        assert_eq!(Vec::<String>::new(), validate(output.as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn it_keeps_whitespace_content() {
+        let mame = MAME.replace("005 &amp; co", " ");
+        let (output, _) = merged(&mame, EXTRAS, &config("extras", HeaderRule::Mame)).unwrap();
+        assert!(output.contains(r#"<machine name="005"><description> </description><rom "#));
+    }
+
+    #[test]
+    fn it_combines_headers() {
+        let (output, _) = merged(MAME, EXTRAS, &config("extras", HeaderRule::Combined)).unwrap();
+        assert!(output.contains(
+            "<header><name>MAME + Extras</name><description>MAME 0.264 ROMs (merged) + MAME 0.264 Extras (all content)</description><version>0.264</version></header>"
+        ));
+        let (output, _) = merged(MAME, EXTRAS, &config("extras", HeaderRule::Extras)).unwrap();
+        assert!(output.contains("<header><name>Extras</name>"));
+    }
+
+    #[test]
+    fn it_detects_dir_collision() {
+        let result = merged(MAME, EXTRAS, &config("005", HeaderRule::Combined));
+        assert!(result.is_err());
+        if let Err(err) = result {
+            assert_eq!(
+                "`005` already exists in mame.dat, choose another dir for Extras content",
+                err.to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn it_warns_on_version_mismatch() {
+        let extras = EXTRAS.replace("<version>0.264", "<version>0.263");
+        let (_, summary) = merged(MAME, &extras, &config("extras", HeaderRule::Combined)).unwrap();
+        assert_eq!(
+            vec!["version mismatch: mame.dat is 0.264, extras.dat is 0.263"],
+            summary.warnings
+        );
+    }
+
+    #[test]
+    fn it_rejects_non_logiqx_dat() {
+        let result = merged(
+            "<mame><machine name=\"005\"/></mame>",
+            EXTRAS,
+            &config("extras", HeaderRule::Mame),
+        );
+        assert!(result.is_err());
+        if let Err(err) = result {
+            assert_eq!("mame.dat is not a Logiqx datafile", err.to_string());
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! Split of rom paths into nested dirs and games.
+//!
+//! A rom named `sub\dir\file.ini` in game `folders` is moved to game `dir`,
+//! in dir `sub`, in dir `folders`. Roms without path stay in game `folders`,
+//! so the location of each file is unchanged. Both `\` and `/` are handled
+//! as path separators.
+
+use crate::report::Stats;
+use crate::tree::{Dir, Game, Node, Rom};
+
+/// Files and subfolders found in rom paths of a game.
+#[derive(Default)]
+struct Folder {
+    /// Subfolders, in order of first appearance
+    folders: Vec<(String, Self)>,
+    /// Roms located directly in this folder
+    roms: Vec<Rom>,
+}
+
+impl Folder {
+    /// Insert specified rom at specified folder path.
+    fn insert(&mut self, path: &[&str], rom: Rom) {
+        let Some((first, rest)) = path.split_first() else {
+            self.roms.push(rom);
+            return;
+        };
+        let index = if let Some(index) = self.folders.iter().position(|(name, _)| name == first) {
+            index
+        } else {
+            self.folders.push(((*first).to_string(), Self::default()));
+            self.folders.len() - 1
+        };
+        self.folders[index].1.insert(rest, rom);
+    }
+
+    /// Convert this folder, located at specified path, to a game holding its roms
+    /// and a dir holding its subfolders. Games located in subfolders are described by their path.
+    fn into_nodes(
+        self,
+        name: String,
+        description: Option<String>,
+        path: &str,
+        source: &str,
+    ) -> Vec<Node> {
+        let mut nodes = Vec::new();
+
+        if !self.roms.is_empty() || self.folders.is_empty() {
+            nodes.push(Node::Game(Game {
+                name: name.clone(),
+                description,
+                roms: self.roms,
+                source: source.to_string(),
+            }));
+        }
+
+        if !self.folders.is_empty() {
+            let children = self
+                .folders
+                .into_iter()
+                .flat_map(|(folder_name, folder)| {
+                    let folder_path = format!("{path}/{folder_name}");
+                    folder.into_nodes(folder_name, Some(folder_path.clone()), &folder_path, source)
+                })
+                .collect();
+            nodes.push(Node::Dir(Dir { name, children }));
+        }
+
+        nodes
+    }
+}
+
+/// Split rom paths of all games in specified nodes into nested dirs and games.
+///
+/// Returns the number of games created, beyond the games which were split.
+pub fn split_paths(nodes: &mut Vec<Node>) -> usize {
+    let mut split = Vec::with_capacity(nodes.len());
+    let mut created = 0;
+    for node in nodes.drain(..) {
+        match node {
+            Node::Dir(mut dir) => {
+                created += split_paths(&mut dir.children);
+                split.push(Node::Dir(dir));
+            }
+            Node::Game(game) => {
+                let game_nodes = split_game(game);
+                created += Stats::count(&game_nodes).games.saturating_sub(1);
+                split.extend(game_nodes);
+            }
+        }
+    }
+    *nodes = split;
+
+    created
+}
+
+/// Split rom paths of specified game.
+fn split_game(game: Game) -> Vec<Node> {
+    if !game.roms.iter().any(|rom| rom.name().contains(['\\', '/'])) {
+        return vec![Node::Game(game)];
+    }
+
+    let mut root = Folder::default();
+    for mut rom in game.roms {
+        let name = rom.name();
+        let components: Vec<&str> = name
+            .split(['\\', '/'])
+            .filter(|component| !component.is_empty())
+            .collect();
+        let Some((file, path)) = components.split_last() else {
+            root.roms.push(rom);
+            continue;
+        };
+        if !path.is_empty() {
+            rom.set_name(file);
+        }
+        root.insert(path, rom);
+    }
+
+    let path = game.name.clone();
+    root.into_nodes(game.name, game.description, &path, &game.source)
+}
+
+#[cfg(test)]
+mod tests {
+    use quick_xml::events::BytesStart;
+
+    use super::*;
+
+    fn game(roms: &[&str]) -> Node {
+        Node::Game(Game {
+            name: String::from("folders"),
+            description: Some(String::from("folders")),
+            roms: roms
+                .iter()
+                .map(|rom| Rom {
+                    element: BytesStart::from_content(format!(r#"rom name="{rom}" size="1""#), 3)
+                        .into_owned(),
+                })
+                .collect(),
+            source: String::from("all_non-zipped_content.dat"),
+        })
+    }
+
+    /// Describe nodes as `dir(children)` and `game[description](roms)`.
+    fn describe(nodes: &[Node]) -> String {
+        nodes
+            .iter()
+            .map(|node| match node {
+                Node::Dir(dir) => format!("{}({})", dir.name, describe(&dir.children)),
+                Node::Game(game) => format!(
+                    "{}[{}]({})",
+                    game.name,
+                    game.description.clone().unwrap_or_default(),
+                    game.roms
+                        .iter()
+                        .map(Rom::name)
+                        .collect::<Vec<_>>()
+                        .join(",")
+                ),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn it_keeps_games_without_paths() {
+        let mut nodes = vec![game(&["a.ini", "b.ini"])];
+        assert_eq!(0, split_paths(&mut nodes));
+        assert_eq!("folders[folders](a.ini,b.ini)", describe(&nodes));
+    }
+
+    #[test]
+    fn it_splits_paths() {
+        let mut nodes = vec![Node::Dir(Dir {
+            name: String::from("folders"),
+            children: vec![game(&[
+                "README.txt",
+                "Folders\\category.ini",
+                "Folders/Sub &amp; co\\x.ini",
+                "Folders\\genre.ini",
+            ])],
+        })];
+        assert_eq!(2, split_paths(&mut nodes));
+        assert_eq!(
+            "folders(folders[folders](README.txt) folders(Folders[folders/Folders](category.ini,genre.ini) Folders(Sub &amp; co[folders/Folders/Sub &amp; co](x.ini))))",
+            describe(&nodes)
+        );
+    }
+
+    #[test]
+    fn it_drops_root_game_without_root_roms() {
+        let mut nodes = vec![game(&["/a/b.ini", "a//c.ini"])];
+        assert_eq!(0, split_paths(&mut nodes));
+        assert_eq!("folders(a[folders/a](b.ini,c.ini))", describe(&nodes));
+    }
+}
@@ -0,0 +1,80 @@
+//! Pool of scoped worker threads mapping items in parallel.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::thread;
+
+/// Apply specified function to all items, running up to specified number of named threads in parallel.
+///
+/// Returns the result of each item, in items order. Items are processed in the calling thread
+/// if no thread can be spawned. A panic of `f` is not caught: it is raised again once all
+/// threads have finished, and aborts release builds.
+pub fn map_parallel<T, R, F>(
+    items: &[T],
+    jobs: usize,
+    name: impl Fn(usize) -> String,
+    f: F,
+) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<R>>> = Mutex::new(items.iter().map(|_| None).collect());
+
+    thread::scope(|scope| {
+        for job in 0..jobs.clamp(1, items.len().max(1)) {
+            let (next, results, f) = (&next, &results, &f);
+            let spawned =
+                thread::Builder::new()
+                    .name(name(job))
+                    .spawn_scoped(scope, move || loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(item) = items.get(index) else {
+                            break;
+                        };
+                        let result = f(item);
+                        if let Ok(mut results) = results.lock() {
+                            results[index] = Some(result);
+                        }
+                    });
+            if spawned.is_err() {
+                // Remaining items are processed by spawned jobs
+                break;
+            }
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap_or_else(PoisonError::into_inner)
+        .into_iter()
+        .zip(items)
+        .map(|(result, item)| result.unwrap_or_else(|| f(item)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_preserves_items_order() {
+        let items: Vec<usize> = (0..100).collect();
+        let results = map_parallel(&items, 4, |job| format!("test-{job}"), |item| item * 2);
+        let expected: Vec<usize> = (0..100).map(|item| item * 2).collect();
+        assert_eq!(expected, results);
+    }
+
+    #[test]
+    fn it_names_threads() {
+        let results = map_parallel(
+            &[()],
+            1,
+            |job| format!("test-{job}"),
+            |()| thread::current().name().map(String::from),
+        );
+        assert_eq!(vec![Some(String::from("test-0"))], results);
+    }
+}
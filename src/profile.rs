@@ -0,0 +1,350 @@
+//! Profiles describing supported multi-dat bundles.
+//!
+//! A profile declares the naming pattern of input files, the headers of the generated dat,
+//! and the mapping of expected entries to output dirs. MAME Extras is the default profile;
+//! HBMAME Extras, MAME Multimedia and MAME Software List CHDs are also built in, from the
+//! TOML files of the `profiles` directory. Other bundles can be described with a TOML file, e.g.:
+//!
+//! ```toml
+//! pattern = '(?:^|[\\/])HBMAME (?<version>\d\.\d+) EXTRAs\.zip$'
+//!
+//! [header]
+//! name = "HBMAME Extras"
+//! description = "HBMAME {version} Extras (all content)"
+//! url = "https://pleasuredome.miraheze.org/wiki/HBMAME"
+//!
+//! [[source]]
+//! entry = "artwork.dat"
+//! root_dir = "artwork"
+//! ```
+
+use anyhow::anyhow;
+use regex::Regex;
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+use crate::files::{extract_version_matching, EXTRAS_PATTERN};
+use crate::mapping::{Mapping, Source};
+
+/// Custom result with any context error.
+type Result<T> = anyhow::Result<T>;
+
+/// Name of the built-in MAME Extras profile.
+pub const MAME_EXTRAS: &str = "mame-extras";
+/// Built-in profiles other than MAME Extras, with their TOML description.
+const BUILTIN_PROFILES: [(&str, &str); 3] = [
+    (
+        "hbmame-extras",
+        include_str!("../profiles/hbmame-extras.toml"),
+    ),
+    (
+        "mame-multimedia",
+        include_str!("../profiles/mame-multimedia.toml"),
+    ),
+    (
+        "mame-sl-chds",
+        include_str!("../profiles/mame-sl-chds.toml"),
+    ),
+];
+/// Placeholder replaced by version in dat description.
+const VERSION_PLACEHOLDER: &str = "{version}";
+
+/// Headers of generated dat.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Header {
+    /// Dat name.
+    pub name: String,
+    /// Dat description. `{version}` is replaced by the version extracted from input file name.
+    pub description: String,
+    /// Dat author.
+    #[serde(default = "default_author")]
+    pub author: String,
+    /// URL describing the bundle.
+    pub url: String,
+}
+
+/// Default author of generated dats.
+fn default_author() -> String {
+    String::from("Pleasuredome")
+}
+
+/// Description of a multi-dat bundle.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(from = "ProfileFile")]
+pub struct Profile {
+    /// Case-insensitive regular expression matching input file names, with a `version` named group.
+    pub pattern: String,
+    /// Headers of generated dat.
+    pub header: Header,
+    /// Expected entries and their placement in output.
+    pub mapping: Mapping,
+}
+
+/// Profile as written in a TOML file, with sources at top level.
+/// Serde does not reject unknown fields of flattened structs, so sources are read here.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProfileFile {
+    /// Case-insensitive regular expression matching input file names.
+    pattern: String,
+    /// Headers of generated dat.
+    header: Header,
+    /// Source dats.
+    #[serde(rename = "source")]
+    sources: Vec<Source>,
+}
+
+impl From<ProfileFile> for Profile {
+    fn from(file: ProfileFile) -> Self {
+        Self {
+            pattern: file.pattern,
+            header: file.header,
+            mapping: Mapping {
+                sources: file.sources,
+            },
+        }
+    }
+}
+
+impl Default for Profile {
+    /// Built-in MAME Extras profile.
+    fn default() -> Self {
+        Self {
+            pattern: String::from(EXTRAS_PATTERN),
+            header: Header {
+                name: String::from("Extras"),
+                description: String::from("MAME {version} Extras (all content)"),
+                author: default_author(),
+                url: String::from("https://pleasuredome.miraheze.org/wiki/MAME_EXTRAs"),
+            },
+            mapping: Mapping::default(),
+        }
+    }
+}
+
+impl Header {
+    /// Dat description for specified version. Without version, the `{version}` placeholder is removed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use convert_mame_extras_romvault::profile::Profile;
+    ///
+    /// let header = Profile::default().header;
+    /// assert_eq!("MAME 0.264 Extras (all content)", header.description(Some(0.264)));
+    /// assert_eq!("MAME Extras (all content)", header.description(None));
+    /// ```
+    #[must_use]
+    pub fn description(&self, version: Option<f32>) -> String {
+        version.map_or_else(
+            || {
+                self.description
+                    .replace(&format!("{VERSION_PLACEHOLDER} "), "")
+                    .replace(VERSION_PLACEHOLDER, "")
+            },
+            |version| {
+                self.description
+                    .replace(VERSION_PLACEHOLDER, &version.to_string())
+            },
+        )
+    }
+}
+
+impl Profile {
+    /// Parse profile from specified TOML content.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if content is not a valid profile, if pattern is not a valid
+    /// regular expression with a `version` group, or if no source is declared.
+    pub fn parse(content: &str) -> Result<Self> {
+        let profile: Self = toml::from_str(content)?;
+        let pattern = Regex::new(&profile.pattern)
+            .map_err(|err| anyhow!("invalid pattern `{}`: {err}", profile.pattern))?;
+        if !pattern.capture_names().any(|name| name == Some("version")) {
+            return Err(anyhow!(
+                "pattern `{}` must contain a `version` named group",
+                profile.pattern
+            ));
+        }
+        if profile.mapping.sources.is_empty() {
+            return Err(anyhow!("profile must declare at least one source"));
+        }
+
+        Ok(profile)
+    }
+
+    /// Names of built-in profiles.
+    pub fn builtin_names() -> impl Iterator<Item = &'static str> {
+        std::iter::once(MAME_EXTRAS).chain(BUILTIN_PROFILES.iter().map(|(name, _)| *name))
+    }
+
+    /// Load built-in profile with specified name, or profile from specified TOML file.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if profile is not built-in and file cannot be read or is not a valid profile.
+    pub fn load(name_or_path: &str) -> Result<Self> {
+        if name_or_path == MAME_EXTRAS {
+            return Ok(Self::default());
+        }
+        if let Some((_, content)) = BUILTIN_PROFILES
+            .iter()
+            .find(|(name, _)| *name == name_or_path)
+        {
+            return Self::parse(content)
+                .map_err(|err| anyhow!("invalid built-in profile `{name_or_path}`: {err}"));
+        }
+
+        let path = Path::new(name_or_path);
+        let content = fs::read_to_string(path).map_err(|err| {
+            anyhow!(
+                "unknown profile `{name_or_path}` (built-in profiles are {}): {err}",
+                Self::builtin_names().collect::<Vec<_>>().join(", ")
+            )
+        })?;
+        Self::parse(&content).map_err(|err| anyhow!("invalid profile `{}`: {err}", path.display()))
+    }
+
+    /// Tries to extract version from specified input file name, using profile pattern.
+    #[must_use]
+    pub fn extract_version(&self, file_name: &str) -> Option<f32> {
+        extract_version_matching(file_name, &self.pattern)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Built-in profile written as TOML.
+    const DEFAULT_PROFILE: &str = r#"
+        pattern = '(?:^|[\\/])MAME (?<version>\d\.\d+) EXTRAs\.zip$'
+
+        [header]
+        name = "Extras"
+        description = "MAME {version} Extras (all content)"
+        url = "https://pleasuredome.miraheze.org/wiki/MAME_EXTRAs"
+
+        [[source]]
+        entry = "all_non-zipped_content.dat"
+        dirs = ["dats", "folders"]
+
+        [[source]]
+        entry = "artwork.dat"
+        root_dir = "artwork"
+
+        [[source]]
+        entry = "samples.dat"
+        root_dir = "samples"
+    "#;
+
+    #[test]
+    fn it_parses_profile() {
+        let profile = Profile::parse(DEFAULT_PROFILE);
+        assert!(profile.is_ok());
+        assert_eq!(Profile::default(), profile.unwrap());
+    }
+
+    #[test]
+    fn it_loads_builtin_profile() {
+        let profile = Profile::load(MAME_EXTRAS);
+        assert!(profile.is_ok());
+        assert_eq!(Profile::default(), profile.unwrap());
+    }
+
+    #[test]
+    fn it_handles_unknown_profile() {
+        let result = Profile::load("foo");
+        assert!(result.is_err());
+        if let Err(err) = result {
+            assert!(err
+                .to_string()
+                .starts_with("unknown profile `foo` (built-in profiles are mame-extras, hbmame-extras, mame-multimedia, mame-sl-chds)"));
+        }
+    }
+
+    #[test]
+    fn it_rejects_pattern_without_version() {
+        let content = DEFAULT_PROFILE.replace("(?<version>", "(");
+        let result = Profile::parse(&content);
+        assert!(result.is_err());
+        if let Err(err) = result {
+            assert_eq!(
+                r"pattern `(?:^|[\\/])MAME (\d\.\d+) EXTRAs\.zip$` must contain a `version` named group",
+                err.to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn it_loads_all_builtin_profiles() {
+        for name in Profile::builtin_names() {
+            let profile = Profile::load(name);
+            assert!(profile.is_ok(), "{name}: {profile:?}");
+        }
+        let profile = Profile::load("hbmame-extras").unwrap();
+        assert_eq!(
+            Some(0.245),
+            profile.extract_version("HBMAME 0.245 EXTRAs.zip")
+        );
+        let profile = Profile::load("mame-sl-chds").unwrap();
+        assert_eq!(
+            Some(0.264),
+            profile.extract_version("MAME 0.264 Software List CHDs.zip")
+        );
+        assert_eq!(
+            None,
+            profile.extract_version("HBMAME 0.264 Software List CHDs.zip")
+        );
+        let profile = Profile::load("mame-multimedia").unwrap();
+        assert_eq!(
+            Some(0.264),
+            profile.extract_version("dats/MAME 0.264 Multimedia.zip")
+        );
+        assert_eq!(None, profile.extract_version("HBMAME 0.264 Multimedia.zip"));
+    }
+
+    #[test]
+    fn it_rejects_hbmame_files_with_default_profile() {
+        let profile = Profile::default();
+        assert_eq!(None, profile.extract_version("HBMAME 0.245 EXTRAs.zip"));
+        assert_eq!(
+            None,
+            profile.extract_version("dats/HBMAME 0.245 EXTRAs.zip")
+        );
+        assert_eq!(
+            Some(0.264),
+            profile.extract_version("dats\\MAME 0.264 EXTRAs.zip")
+        );
+    }
+
+    #[test]
+    fn it_rejects_unknown_fields() {
+        let content = format!("patern = 'a'\n{DEFAULT_PROFILE}");
+        let result = Profile::parse(&content);
+        assert!(result.is_err());
+        if let Err(err) = result {
+            assert!(err.to_string().contains("unknown field `patern`"));
+        }
+    }
+
+    #[test]
+    fn it_rejects_unknown_source_fields() {
+        let content = DEFAULT_PROFILE.replace("root_dir = \"samples\"", "root = \"samples\"");
+        assert!(Profile::parse(&content).is_err());
+    }
+
+    #[test]
+    fn it_extracts_version_with_profile_pattern() {
+        let content = DEFAULT_PROFILE.replace("MAME (", "HBMAME (");
+        let profile = Profile::parse(&content).unwrap();
+        assert_eq!(
+            Some(0.245),
+            profile.extract_version("HBMAME 0.245 EXTRAs.zip")
+        );
+        assert_eq!(None, profile.extract_version("MAME 0.245 Multimedia.zip"));
+    }
+}
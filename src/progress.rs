@@ -0,0 +1,233 @@
+//! Progress of a conversion, reported for each source dat.
+//!
+//! Progress is measured as the number of bytes of the source dat read so far, compared to
+//! the uncompressed size of its Zip entry, along with the number of machines processed.
+//! Updates are sent to a callback, at most every [`UPDATE_INTERVAL`] and once the source
+//! dat is fully read.
+//!
+//! The command line displays progress as terminal progress bars, or as log lines
+//! every [`LOG_INTERVAL`] when standard error is not a terminal.
+
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use log::info;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Minimum delay between two updates of a source dat.
+pub const UPDATE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Delay between two log lines of a source dat.
+pub const LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Template of progress bars (see [`ProgressStyle::with_template`]).
+const BAR_TEMPLATE: &str =
+    "{prefix:>28} [{bar:30}] {percent:>3}% {binary_bytes}/{binary_total_bytes} {msg}";
+
+/// Progress of a source dat.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Progress<'a> {
+    /// Source dat name (e.g. `artwork.dat`)
+    pub dat: &'a str,
+    /// Bytes of source dat read so far
+    pub bytes_read: u64,
+    /// Uncompressed size of source dat
+    pub total_bytes: u64,
+    /// Machines processed so far
+    pub machines: usize,
+    /// Whether source dat is fully read
+    pub done: bool,
+}
+
+impl Progress<'_> {
+    /// Percentage of source dat read.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use convert_mame_extras_romvault::progress::Progress;
+    ///
+    /// let progress = Progress { dat: "artwork.dat", bytes_read: 50, total_bytes: 200, machines: 3, done: false };
+    /// assert_eq!(25, progress.percent());
+    /// ```
+    #[must_use]
+    pub fn percent(&self) -> u64 {
+        if self.total_bytes == 0 {
+            return 100;
+        }
+        (self.bytes_read.min(self.total_bytes) * 100) / self.total_bytes
+    }
+}
+
+/// Callback receiving progress updates, called from the thread converting each source dat.
+pub type ProgressCallback = Arc<dyn Fn(&Progress) + Send + Sync>;
+
+/// Tracker of progress of a source dat, sending updates to an optional callback.
+pub(crate) struct Tracker<'a> {
+    /// Callback receiving updates, nothing is tracked if `None`
+    callback: Option<&'a ProgressCallback>,
+    /// Source dat name
+    dat: &'a str,
+    /// Uncompressed size of source dat
+    total_bytes: u64,
+    /// Bytes of source dat read so far
+    bytes_read: u64,
+    /// Machines processed so far
+    machines: usize,
+    /// Time of last update
+    last_update: Instant,
+}
+
+impl<'a> Tracker<'a> {
+    /// Create tracker of specified source dat, and send initial update.
+    pub(crate) fn new(
+        callback: Option<&'a ProgressCallback>,
+        dat: &'a str,
+        total_bytes: u64,
+    ) -> Self {
+        let tracker = Self {
+            callback,
+            dat,
+            total_bytes,
+            bytes_read: 0,
+            machines: 0,
+            last_update: Instant::now(),
+        };
+        tracker.send(false);
+        tracker
+    }
+
+    /// Record a processed machine, read with specified bytes since previous machine,
+    /// and send an update if last one is old enough.
+    pub(crate) fn machine(&mut self, bytes: u64) {
+        if self.callback.is_none() {
+            return;
+        }
+        self.machines += 1;
+        self.bytes_read += bytes;
+        if self.last_update.elapsed() >= UPDATE_INTERVAL {
+            self.last_update = Instant::now();
+            self.send(false);
+        }
+    }
+
+    /// Send final update, with source dat fully read.
+    pub(crate) fn finish(&mut self) {
+        self.bytes_read = self.total_bytes;
+        self.send(true);
+    }
+
+    /// Send an update to callback, if any.
+    fn send(&self, done: bool) {
+        if let Some(callback) = self.callback {
+            callback(&Progress {
+                dat: self.dat,
+                bytes_read: self.bytes_read,
+                total_bytes: self.total_bytes,
+                machines: self.machines,
+                done,
+            });
+        }
+    }
+}
+
+/// Build callback displaying a progress bar for each source dat on standard error.
+#[must_use]
+pub fn progress_bars() -> ProgressCallback {
+    let bars = MultiProgress::new();
+    let style = ProgressStyle::with_template(BAR_TEMPLATE)
+        .unwrap_or_else(|_| ProgressStyle::default_bar())
+        .progress_chars("=> ");
+    let by_dat: Mutex<HashMap<String, ProgressBar>> = Mutex::new(HashMap::new());
+
+    Arc::new(move |progress: &Progress| {
+        let Ok(mut by_dat) = by_dat.lock() else {
+            return;
+        };
+        let bar = by_dat.entry(String::from(progress.dat)).or_insert_with(|| {
+            let bar = bars.add(ProgressBar::new(progress.total_bytes));
+            bar.set_style(style.clone());
+            bar.set_prefix(String::from(progress.dat));
+            bar
+        });
+        bar.set_position(progress.bytes_read);
+        bar.set_message(format!("{} machines", progress.machines));
+        if progress.done {
+            bar.finish();
+        }
+    })
+}
+
+/// Build callback logging progress of each source dat every [`LOG_INTERVAL`].
+///
+/// Completion of a source dat is only logged if its progress was logged before,
+/// so that short conversions stay silent.
+#[must_use]
+pub fn progress_logs() -> ProgressCallback {
+    let start = Instant::now();
+    let last_logs: Mutex<HashMap<String, Instant>> = Mutex::new(HashMap::new());
+
+    Arc::new(move |progress: &Progress| {
+        let Ok(mut last_logs) = last_logs.lock() else {
+            return;
+        };
+        let last_log = last_logs.get(progress.dat).copied();
+        let due = last_log.unwrap_or(start).elapsed() >= LOG_INTERVAL;
+        if (progress.done && last_log.is_some()) || (!progress.done && due) {
+            info!(
+                "{}: {}% ({}/{} bytes, {} machines){}",
+                progress.dat,
+                progress.percent(),
+                progress.bytes_read,
+                progress.total_bytes,
+                progress.machines,
+                if progress.done { ", done" } else { "" }
+            );
+            last_logs.insert(String::from(progress.dat), Instant::now());
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    #[test]
+    fn it_sends_initial_and_final_updates() {
+        let updates = Arc::new(Mutex::new(Vec::new()));
+        let received = Arc::clone(&updates);
+        let callback: ProgressCallback = Arc::new(move |progress: &Progress| {
+            received
+                .lock()
+                .unwrap()
+                .push((progress.bytes_read, progress.machines, progress.done));
+        });
+
+        let mut tracker = Tracker::new(Some(&callback), "artwork.dat", 100);
+        tracker.machine(40);
+        tracker.machine(40);
+        tracker.finish();
+
+        // Intermediate updates are throttled
+        assert_eq!(
+            vec![(0, 0, false), (100, 2, true)],
+            *updates.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn it_computes_percent() {
+        let progress = |bytes_read, total_bytes| Progress {
+            dat: "samples.dat",
+            bytes_read,
+            total_bytes,
+            machines: 0,
+            done: false,
+        };
+        assert_eq!(0, progress(0, 10).percent());
+        assert_eq!(100, progress(12, 10).percent());
+        assert_eq!(100, progress(0, 0).percent());
+    }
+}
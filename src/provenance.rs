@@ -0,0 +1,155 @@
+//! Provenance of generated dats, recorded in their header comment.
+//!
+//! Provenance lists the converter version, the input bundle file name, size and SHA-1 digest,
+//! and the name and version declared in the header of each converted source dat, so that a
+//! dat found later in the dat root of a rom manager can be traced back to its source.
+
+use anyhow::anyhow;
+use quick_xml::reader::Reader;
+use std::fmt;
+use std::fs;
+use std::io::BufReader;
+
+use crate::checksum::sha1_file;
+use crate::info::read_header;
+use crate::zip::SharedArchive;
+use crate::Config;
+
+/// Custom result with any context error.
+type Result<T> = anyhow::Result<T>;
+
+/// Header of a converted source dat.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceHeader {
+    /// Source dat name (e.g. `artwork.dat`)
+    pub dat: String,
+    /// Name declared in header
+    pub name: Option<String>,
+    /// Version declared in header
+    pub version: Option<String>,
+}
+
+/// Provenance of a generated dat.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Provenance {
+    /// Version of converter
+    pub tool_version: String,
+    /// Input Zip file name
+    pub input_file: String,
+    /// Size of input file, in bytes
+    pub input_size: u64,
+    /// SHA-1 digest of input file, hex encoded
+    pub input_sha1: String,
+    /// Headers of converted source dats
+    pub sources: Vec<SourceHeader>,
+}
+
+impl Provenance {
+    /// Build provenance of conversion with specified configuration, from specified input Zip file.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if input file cannot be read, or if a source dat header is not valid XML.
+    pub fn new(config: &Config, archive: &SharedArchive) -> Result<Self> {
+        let path = &config.input_file_path;
+        let metadata =
+            fs::metadata(path).map_err(|err| anyhow!("cannot read {}: {err}", path.display()))?;
+        let mut archive = archive.clone();
+        let mut sources = Vec::new();
+        for source in config
+            .profile
+            .mapping
+            .selected_sources(config.sections.as_deref())
+        {
+            let entry = archive.by_name(&source.entry)?;
+            let (header, _) = read_header(
+                &mut Reader::from_reader(BufReader::new(entry)),
+                &source.entry,
+            )?;
+            let field = |name: &str| {
+                header
+                    .iter()
+                    .find(|field| field.name == name)
+                    .map(|field| field.value.clone())
+            };
+            sources.push(SourceHeader {
+                dat: source.entry.clone(),
+                name: field("name"),
+                version: field("version"),
+            });
+        }
+
+        Ok(Self {
+            tool_version: String::from(env!("CARGO_PKG_VERSION")),
+            input_file: path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned(),
+            input_size: metadata.len(),
+            input_sha1: sha1_file(path)?,
+            sources,
+        })
+    }
+}
+
+impl fmt::Display for Provenance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "converted by {} {} from {} ({} bytes, SHA-1 {})",
+            env!("CARGO_PKG_NAME"),
+            self.tool_version,
+            self.input_file,
+            self.input_size,
+            self.input_sha1
+        )?;
+        for source in &self.sources {
+            write!(f, "; {}:", source.dat)?;
+            match (&source.name, &source.version) {
+                (None, None) => write!(f, " no header")?,
+                (name, version) => {
+                    if let Some(name) = name {
+                        write!(f, " {name}")?;
+                    }
+                    if let Some(version) = version {
+                        write!(f, " (version {version})")?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_formats_provenance() {
+        let provenance = Provenance {
+            tool_version: String::from("1.0.1"),
+            input_file: String::from("MAME 0.264 EXTRAs.zip"),
+            input_size: 1234,
+            input_sha1: String::from("a9993e364706816aba3e25717850c26c9cd0d89d"),
+            sources: vec![
+                SourceHeader {
+                    dat: String::from("artwork.dat"),
+                    name: Some(String::from("MAME 0.264 Artwork")),
+                    version: Some(String::from("0.264")),
+                },
+                SourceHeader {
+                    dat: String::from("samples.dat"),
+                    name: None,
+                    version: None,
+                },
+            ],
+        };
+        assert_eq!(
+            "converted by convert-mame-extras-romvault 1.0.1 from MAME 0.264 EXTRAs.zip (1234 bytes, SHA-1 a9993e364706816aba3e25717850c26c9cd0d89d); artwork.dat: MAME 0.264 Artwork (version 0.264); samples.dat: no header",
+            provenance.to_string()
+        );
+    }
+}
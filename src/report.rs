@@ -0,0 +1,175 @@
+//! Machine-readable report of a conversion.
+//!
+//! The report is a JSON document with the input and output files, the detected version,
+//! counts of generated dirs, games and roms for each source dat and for the whole output,
+//! warnings, timings and the SHA-256 digest of the output file.
+
+use anyhow::anyhow;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::checksum::sha256_file;
+use crate::dat::Summary;
+use crate::filter::Filtered;
+use crate::tree::{Node, Rom};
+use crate::Config;
+
+/// Custom result with any context error.
+type Result<T> = anyhow::Result<T>;
+
+/// Number of items generated in a section or in the whole output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Stats {
+    /// Number of dirs
+    pub dirs: usize,
+    /// Number of games
+    pub games: usize,
+    /// Number of roms
+    pub roms: usize,
+    /// Sum of declared rom sizes, in bytes
+    pub bytes: u64,
+}
+
+impl Stats {
+    /// Count dirs, games and roms of specified nodes, recursively.
+    #[must_use]
+    pub fn count(nodes: &[Node]) -> Self {
+        let mut stats = Self::default();
+        for node in nodes {
+            match node {
+                Node::Dir(dir) => {
+                    stats.dirs += 1;
+                    stats.add(Self::count(&dir.children));
+                }
+                Node::Game(game) => {
+                    stats.games += 1;
+                    stats.roms += game.roms.len();
+                    stats.bytes += game.roms.iter().filter_map(Rom::size).sum::<u64>();
+                }
+            }
+        }
+
+        stats
+    }
+
+    /// Add specified counts to these counts.
+    const fn add(&mut self, other: Self) {
+        self.dirs += other.dirs;
+        self.games += other.games;
+        self.roms += other.roms;
+        self.bytes += other.bytes;
+    }
+}
+
+/// Statistics of a source dat, generated in its own thread.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SectionStats {
+    /// Source dat name (e.g. `artwork.dat`)
+    pub dat: String,
+    /// Items generated from source dat, before duplicates are resolved
+    pub stats: Stats,
+    /// Time spent by thread, in milliseconds
+    pub elapsed_ms: u128,
+}
+
+/// Report of a conversion.
+#[derive(Debug, Serialize)]
+pub struct Report {
+    /// Input Zip file
+    pub input_file: PathBuf,
+    /// Version extracted from input file name
+    pub version: Option<f32>,
+    /// Output dat file
+    pub output_file: PathBuf,
+    /// Statistics of each source dat
+    pub sections: Vec<SectionStats>,
+    /// Items written to output, after duplicates are resolved
+    pub totals: Stats,
+    /// Warnings raised during generation
+    pub warnings: Vec<String>,
+    /// Games and roms dropped by filters
+    pub filtered: Filtered,
+    /// Total time of conversion, in milliseconds
+    pub elapsed_ms: u128,
+    /// SHA-256 digest of output file, hex encoded
+    pub output_sha256: String,
+}
+
+impl Report {
+    /// Build report of conversion with specified configuration and summary.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if output file cannot be read.
+    pub fn new(config: &Config, summary: Summary, elapsed: Duration) -> Result<Self> {
+        Ok(Self {
+            input_file: config.input_file_path.clone(),
+            version: config.version,
+            output_sha256: sha256_file(&config.output_file_path)?,
+            output_file: config.output_file_path.clone(),
+            sections: summary.sections,
+            totals: summary.totals,
+            warnings: summary.warnings,
+            filtered: summary.filtered,
+            elapsed_ms: elapsed.as_millis(),
+        })
+    }
+
+    /// Write report as JSON to specified file.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if report cannot be written.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)? + "\n";
+        fs::write(path, json).map_err(|err| anyhow!("cannot write {}: {err}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use quick_xml::events::BytesStart;
+
+    use super::*;
+    use crate::tree::{Dir, Game};
+
+    fn game(name: &str, sizes: &[&str]) -> Node {
+        Node::Game(Game {
+            name: String::from(name),
+            description: None,
+            roms: sizes
+                .iter()
+                .enumerate()
+                .map(|(index, size)| {
+                    let mut element = BytesStart::new("rom");
+                    element.push_attribute(("name", index.to_string().as_str()));
+                    element.push_attribute(("size", *size));
+                    Rom { element }
+                })
+                .collect(),
+            source: String::from("artwork.dat"),
+        })
+    }
+
+    #[test]
+    fn it_counts_nodes() {
+        let nodes = vec![
+            Node::Dir(Dir {
+                name: String::from("artwork"),
+                children: vec![game("005", &["10", "20"]), game("10yard", &["bad"])],
+            }),
+            game("dats", &["5"]),
+        ];
+        assert_eq!(
+            Stats {
+                dirs: 1,
+                games: 3,
+                roms: 4,
+                bytes: 35
+            },
+            Stats::count(&nodes)
+        );
+    }
+}
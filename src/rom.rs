@@ -0,0 +1,164 @@
+//! Validation of rom entries.
+
+use clap::ValueEnum;
+use quick_xml::events::attributes::Attribute;
+use quick_xml::events::BytesStart;
+use quick_xml::name::QName;
+use std::borrow::Cow;
+
+/// Behaviour when a malformed rom entry is found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum RomCheck {
+    /// Report malformed entries and keep converting.
+    Warn,
+    /// Stop the conversion on the first malformed entry.
+    Error,
+}
+
+/// Hash attributes which can be found on a rom, with their expected length.
+const HASHES: [(&str, usize); 3] = [("crc", 8), ("sha1", 40), ("md5", 32)];
+
+/// Check rom attributes and return a copy of the rom with hashes normalized to lowercase,
+/// along with a description of every problem found.
+///
+/// Checks are:
+/// - `name` is present and not empty
+/// - `size` is present and numeric
+/// - `crc`, `sha1` and `md5`, when present, are made of 8, 40 and 32 hexadecimal digits
+///
+/// Malformed values are left untouched in the returned rom.
+///
+/// # Examples
+///
+/// ```
+/// use quick_xml::events::BytesStart;
+/// use convert_mame_extras_romvault::rom::check_rom;
+///
+/// let rom = BytesStart::from_content(r#"rom name="a.png" size="12" crc="ABCDEF01""#, 3);
+/// let (rom, problems) = check_rom(&rom);
+/// assert!(problems.is_empty());
+/// assert_eq!(r#"rom name="a.png" size="12" crc="abcdef01""#, std::str::from_utf8(&rom).unwrap());
+/// ```
+#[must_use]
+pub fn check_rom(rom: &BytesStart) -> (BytesStart<'static>, Vec<String>) {
+    let mut problems = Vec::new();
+    let mut normalized = BytesStart::new("rom");
+    let mut name = None;
+    let mut size = None;
+
+    for attribute in rom.attributes() {
+        let Ok(attribute) = attribute else {
+            problems.push(String::from("malformed attribute"));
+            continue;
+        };
+        let key = String::from_utf8_lossy(attribute.key.as_ref()).into_owned();
+        let value = String::from_utf8_lossy(&attribute.value).into_owned();
+
+        match key.as_str() {
+            "name" => name = Some(value.clone()),
+            "size" => size = Some(value.clone()),
+            _ => (),
+        }
+
+        let value = match HASHES.iter().find(|(hash, _)| *hash == key) {
+            Some((hash, length)) => {
+                if value.len() != *length || !value.chars().all(|c| c.is_ascii_hexdigit()) {
+                    problems.push(format!(
+                        "invalid {hash} `{value}` (expected {length} hex digits)"
+                    ));
+                    value
+                } else {
+                    value.to_ascii_lowercase()
+                }
+            }
+            None => value,
+        };
+
+        normalized.push_attribute(Attribute {
+            key: QName(attribute.key.as_ref()),
+            value: Cow::from(value.as_bytes()),
+        });
+    }
+
+    match name.as_deref() {
+        None | Some("") => problems.push(String::from("missing name")),
+        _ => (),
+    }
+    match size.as_deref() {
+        None => problems.push(String::from("missing size")),
+        Some(size) if size.is_empty() || !size.chars().all(|c| c.is_ascii_digit()) => {
+            problems.push(format!("invalid size `{size}`"));
+        }
+        _ => (),
+    }
+
+    (normalized.into_owned(), problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(content: &str) -> (String, Vec<String>) {
+        let rom = BytesStart::from_content(content, 3);
+        let (rom, problems) = check_rom(&rom);
+        (String::from_utf8(rom.to_vec()).unwrap(), problems)
+    }
+
+    #[test]
+    fn it_accepts_valid_rom() {
+        let content = r#"rom name="history.xml" size="100" crc="0a1b2c3d" sha1="0000000000000000000000000000000001234567" md5="0123456789abcdef0123456789abcdef""#;
+        let (rom, problems) = check(content);
+        assert!(problems.is_empty());
+        assert_eq!(content, rom);
+    }
+
+    #[test]
+    fn it_normalizes_hashes_case() {
+        let (rom, problems) = check(
+            r#"rom name="A.PNG" size="1" crc="0A1B2C3D" sha1="ABCDEF0000000000000000000000000001234567""#,
+        );
+        assert!(problems.is_empty());
+        assert_eq!(
+            r#"rom name="A.PNG" size="1" crc="0a1b2c3d" sha1="abcdef0000000000000000000000000001234567""#,
+            rom
+        );
+    }
+
+    #[test]
+    fn it_keeps_escaped_values() {
+        let content = r#"rom name="a &amp; b.png" size="1" crc="0a1b2c3d""#;
+        let (rom, problems) = check(content);
+        assert!(problems.is_empty());
+        assert_eq!(content, rom);
+    }
+
+    #[test]
+    fn it_reports_missing_name_and_size() {
+        let (_, problems) = check(r#"rom crc="0a1b2c3d""#);
+        assert_eq!(vec!["missing name", "missing size"], problems);
+    }
+
+    #[test]
+    fn it_reports_invalid_size() {
+        let (_, problems) = check(r#"rom name="a" size="12k""#);
+        assert_eq!(vec!["invalid size `12k`"], problems);
+    }
+
+    #[test]
+    fn it_reports_invalid_hashes() {
+        let (rom, problems) = check(r#"rom name="a" size="1" crc="0A1B2C" sha1="xyz" md5="0123""#);
+        assert_eq!(
+            vec![
+                "invalid crc `0A1B2C` (expected 8 hex digits)",
+                "invalid sha1 `xyz` (expected 40 hex digits)",
+                "invalid md5 `0123` (expected 32 hex digits)",
+            ],
+            problems
+        );
+        assert_eq!(
+            r#"rom name="a" size="1" crc="0A1B2C" sha1="xyz" md5="0123""#,
+            rom
+        );
+    }
+}
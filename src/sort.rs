@@ -0,0 +1,202 @@
+//! Canonical ordering of generated dirs, games and roms.
+//!
+//! Names are unescaped, then compared case-insensitively (Unicode lowercase, by code point),
+//! ties being broken by comparing the unescaped names exactly. A dir is placed before a game
+//! with the same name, and nodes or roms with the same name (e.g. kept duplicates) are ordered
+//! by their content. Two conversions of the same content in a different order therefore
+//! produce the same output.
+
+use std::cmp::Ordering;
+
+use crate::tree::{display_name, Node};
+
+/// Sort key of a name, following the canonical collation.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct NameKey {
+    /// Lowercase unescaped name
+    folded: String,
+    /// Unescaped name
+    name: String,
+}
+
+impl NameKey {
+    /// Build sort key of specified escaped name.
+    fn new(escaped: &str) -> Self {
+        let name = display_name(escaped);
+        Self {
+            folded: name.to_lowercase(),
+            name,
+        }
+    }
+}
+
+/// Compare two escaped names with the canonical collation.
+///
+/// # Examples
+///
+/// ```
+/// use convert_mame_extras_romvault::sort::compare_names;
+/// use std::cmp::Ordering;
+///
+/// assert_eq!(Ordering::Less, compare_names("Artwork", "dats"));
+/// assert_eq!(Ordering::Less, compare_names("A", "a"));
+/// assert_eq!(Ordering::Less, compare_names("a &amp; b", "a b"));
+/// ```
+#[must_use]
+pub fn compare_names(a: &str, b: &str) -> Ordering {
+    NameKey::new(a).cmp(&NameKey::new(b))
+}
+
+/// Sort specified nodes, the children of their dirs and the roms of their games, recursively.
+pub fn sort_nodes(nodes: &mut [Node]) {
+    for node in nodes.iter_mut() {
+        match node {
+            Node::Dir(dir) => sort_nodes(&mut dir.children),
+            Node::Game(game) => game
+                .roms
+                .sort_by_cached_key(|rom| (NameKey::new(&rom.name()), rom.element.to_vec())),
+        }
+    }
+    nodes.sort_by_cached_key(|node| {
+        (
+            NameKey::new(node.name()),
+            matches!(node, Node::Game(_)),
+            content_key(node),
+        )
+    });
+}
+
+/// Content of specified sorted node written after its name: description and roms of a game,
+/// or children of a dir. Each part is prefixed with its length, so different contents never
+/// give the same key.
+fn content_key(node: &Node) -> Vec<u8> {
+    fn push(key: &mut Vec<u8>, part: &[u8]) {
+        key.extend_from_slice(&part.len().to_be_bytes());
+        key.extend_from_slice(part);
+    }
+
+    let mut key = Vec::new();
+    match node {
+        Node::Dir(dir) => {
+            for child in &dir.children {
+                push(&mut key, &[u8::from(matches!(child, Node::Game(_)))]);
+                push(&mut key, child.name().as_bytes());
+                push(&mut key, &content_key(child));
+            }
+        }
+        Node::Game(game) => {
+            push(
+                &mut key,
+                game.description.as_deref().unwrap_or_default().as_bytes(),
+            );
+            for rom in &game.roms {
+                push(&mut key, &rom.element);
+            }
+        }
+    }
+
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use quick_xml::events::BytesStart;
+
+    use super::*;
+    use crate::tree::{Dir, Game, Rom};
+
+    fn game(name: &str, roms: &[&str]) -> Node {
+        Node::Game(Game {
+            name: String::from(name),
+            description: None,
+            roms: roms
+                .iter()
+                .map(|rom| {
+                    let mut element = BytesStart::new("rom");
+                    element.push_attribute(("name", *rom));
+                    Rom { element }
+                })
+                .collect(),
+            source: String::from("artwork.dat"),
+        })
+    }
+
+    fn dir(name: &str, children: Vec<Node>) -> Node {
+        Node::Dir(Dir {
+            name: String::from(name),
+            children,
+        })
+    }
+
+    fn names(nodes: &[Node]) -> Vec<&str> {
+        nodes.iter().map(Node::name).collect()
+    }
+
+    #[test]
+    fn it_sorts_nodes_recursively() {
+        let mut nodes = vec![
+            dir("samples", vec![game("circus", &[])]),
+            dir(
+                "artwork",
+                vec![game("1942", &[]), game("005", &["z.png", "B.png", "a.png"])],
+            ),
+            game("dats", &[]),
+            dir("dats", vec![]),
+        ];
+        sort_nodes(&mut nodes);
+
+        assert_eq!(vec!["artwork", "dats", "dats", "samples"], names(&nodes));
+        assert!(matches!(nodes[1], Node::Dir(_)));
+        let Node::Dir(artwork) = &nodes[0] else {
+            panic!("artwork is not a dir");
+        };
+        assert_eq!(vec!["005", "1942"], names(&artwork.children));
+        let Node::Game(game) = &artwork.children[0] else {
+            panic!("005 is not a game");
+        };
+        let roms: Vec<String> = game.roms.iter().map(Rom::name).collect();
+        assert_eq!(vec!["a.png", "B.png", "z.png"], roms);
+    }
+
+    #[test]
+    fn it_produces_same_order_from_any_order() {
+        let mut a = vec![game("b", &[]), game("A", &[]), game("a", &[])];
+        let mut b = vec![game("a", &[]), game("b", &[]), game("A", &[])];
+        sort_nodes(&mut a);
+        sort_nodes(&mut b);
+        assert_eq!(names(&a), names(&b));
+        assert_eq!(vec!["A", "a", "b"], names(&a));
+    }
+
+    #[test]
+    fn it_orders_same_names_by_content() {
+        let roms = |nodes: &[Node]| -> Vec<Vec<String>> {
+            nodes
+                .iter()
+                .map(|node| match node {
+                    Node::Game(game) => game.roms.iter().map(Rom::name).collect(),
+                    Node::Dir(_) => Vec::new(),
+                })
+                .collect()
+        };
+        let mut a = vec![game("a", &["2.png"]), game("a", &["1.png", "1.png"])];
+        let mut b = vec![game("a", &["1.png", "1.png"]), game("a", &["2.png"])];
+        sort_nodes(&mut a);
+        sort_nodes(&mut b);
+        assert_eq!(roms(&a), roms(&b));
+
+        let rom = |attributes: &str| Rom {
+            element: BytesStart::from_content(format!("rom {attributes}"), 3).into_owned(),
+        };
+        let Node::Game(mut game) = game("b", &[]) else {
+            panic!("b is not a game");
+        };
+        game.roms = vec![rom(r#"name="a" size="2""#), rom(r#"name="a" size="1""#)];
+        let mut nodes = vec![Node::Game(game)];
+        sort_nodes(&mut nodes);
+        let Node::Game(game) = &nodes[0] else {
+            panic!("b is not a game");
+        };
+        assert_eq!(Some(1), game.roms[0].size());
+    }
+}
@@ -0,0 +1,121 @@
+//! In-memory tree of generated dirs, games and roms.
+//!
+//! Names and descriptions are kept escaped, exactly as read from input dats,
+//! so that writing the tree back produces the same bytes.
+
+use quick_xml::escape::unescape;
+use quick_xml::events::BytesStart;
+
+/// Node of the generated tree.
+pub enum Node {
+    /// A `<dir>` element.
+    Dir(Dir),
+    /// A `<game>` element.
+    Game(Game),
+}
+
+/// A `<dir>` element and its children.
+pub struct Dir {
+    /// Escaped dir name
+    pub name: String,
+    /// Dirs and games contained in this dir
+    pub children: Vec<Node>,
+}
+
+/// A `<game>` element built from an input machine.
+pub struct Game {
+    /// Escaped game name
+    pub name: String,
+    /// Escaped description, if the input machine had one
+    pub description: Option<String>,
+    /// Roms contained in this game
+    pub roms: Vec<Rom>,
+    /// Input dat this game comes from
+    pub source: String,
+}
+
+/// A `<rom>` element.
+pub struct Rom {
+    /// Rom element, with attributes as read from input dat
+    pub element: BytesStart<'static>,
+}
+
+impl Node {
+    /// Escaped name of this node.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Dir(dir) => &dir.name,
+            Self::Game(game) => &game.name,
+        }
+    }
+}
+
+impl Rom {
+    /// Escaped rom name, or an empty string if the rom has no name.
+    #[must_use]
+    pub fn name(&self) -> String {
+        self.element
+            .try_get_attribute("name")
+            .ok()
+            .flatten()
+            .map(|name| String::from_utf8_lossy(&name.value).into_owned())
+            .unwrap_or_default()
+    }
+
+    /// Declared rom size, if present and valid.
+    #[must_use]
+    pub fn size(&self) -> Option<u64> {
+        self.element
+            .try_get_attribute("size")
+            .ok()
+            .flatten()
+            .and_then(|size| String::from_utf8_lossy(&size.value).parse().ok())
+    }
+
+    /// Replace rom name with specified escaped name, keeping other attributes in place.
+    pub fn set_name(&mut self, name: &str) {
+        let mut element = BytesStart::new("rom");
+        for attribute in self.element.attributes().flatten() {
+            if attribute.key.as_ref() == b"name" {
+                element.push_attribute((b"name".as_slice(), name.as_bytes()));
+            } else {
+                element.push_attribute(attribute);
+            }
+        }
+        self.element = element.into_owned();
+    }
+}
+
+/// Unescape specified XML value for display or matching purposes.
+/// The value is returned as is if it is not correctly escaped.
+#[must_use]
+pub fn display_name(escaped: &str) -> String {
+    unescape(escaped).map_or_else(|_| escaped.to_string(), std::borrow::Cow::into_owned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_renames_rom_in_place() {
+        let mut rom = Rom {
+            element: BytesStart::from_content(r#"rom name="a.png" size="1" crc="0a1b2c3d""#, 3)
+                .into_owned(),
+        };
+        rom.set_name("a (2).png");
+        assert_eq!("a (2).png", rom.name());
+        assert_eq!(Some(1), rom.size());
+        assert_eq!(
+            r#"rom name="a (2).png" size="1" crc="0a1b2c3d""#,
+            String::from_utf8(rom.element.to_vec()).unwrap()
+        );
+    }
+
+    #[test]
+    fn it_displays_unescaped_name() {
+        assert_eq!("a & b", display_name("a &amp; b"));
+        assert_eq!("a & b", display_name("a & b"));
+    }
+}
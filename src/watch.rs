@@ -0,0 +1,377 @@
+//! Conversion of bundles as they appear in a directory.
+//!
+//! The directory is monitored with native file system notifications (e.g. inotify on Linux),
+//! or by polling when notifications are not available. A file matching the naming pattern
+//! of the profile is converted once its size is stable and it is a valid Zip file
+//! with expected entries. Files already present when watching starts are converted too,
+//! unless their dat already exists in the output directory (e.g. after a restart).
+//! A file which stays incomplete for longer than the configured timeout is given up with an error,
+//! until it is modified again.
+
+use anyhow::anyhow;
+use log::{debug, error, info, warn};
+use notify::{Event, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use crate::batch::convert;
+use crate::profile::Profile;
+use crate::Config;
+
+/// Custom result with any context error.
+type Result<T> = anyhow::Result<T>;
+
+/// Configuration of watch mode.
+pub struct WatchConfig {
+    /// Directory receiving new Zip files.
+    pub inbox: PathBuf,
+    /// Optional directory receiving converted Zip files.
+    pub processed_dir: Option<PathBuf>,
+    /// Delay between two checks of pending files, and polling interval.
+    pub interval: Duration,
+    /// Poll directory instead of using native notifications.
+    pub poll: bool,
+    /// Delay after which a file whose size stopped changing, but which is still not a valid bundle, is given up.
+    pub timeout: Duration,
+}
+
+/// Size of a pending file.
+#[derive(Debug, Default)]
+struct Observation {
+    /// Size observed at previous check, if any
+    size: Option<u64>,
+    /// Time of the last observed size change
+    changed: Option<Instant>,
+}
+
+/// Sizes of pending files, used to detect files which are fully written.
+#[derive(Debug, Default)]
+struct Pending {
+    /// Observation of each pending file
+    sizes: HashMap<PathBuf, Observation>,
+}
+
+impl Pending {
+    /// Add specified file, unless it is already pending.
+    fn add(&mut self, path: PathBuf) {
+        self.sizes.entry(path).or_default();
+    }
+
+    /// Record specified size of specified file at specified time, or remove it if it no longer exists.
+    ///
+    /// Returns `true` if size did not change since previous check.
+    fn update(&mut self, path: &Path, size: Option<u64>, now: Instant) -> bool {
+        let Some(size) = size else {
+            self.sizes.remove(path);
+            return false;
+        };
+        let observation = self.sizes.entry(path.to_path_buf()).or_default();
+        if observation.size == Some(size) {
+            return true;
+        }
+        observation.size = Some(size);
+        observation.changed = Some(now);
+        false
+    }
+
+    /// Check if size of specified file did not change for longer than specified timeout.
+    fn expired(&self, path: &Path, now: Instant, timeout: Duration) -> bool {
+        self.sizes
+            .get(path)
+            .and_then(|observation| observation.changed)
+            .is_some_and(|changed| now.duration_since(changed) > timeout)
+    }
+
+    /// Pending files, sorted by path.
+    fn paths(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = self.sizes.keys().cloned().collect();
+        paths.sort();
+        paths
+    }
+
+    /// Remove specified file.
+    fn remove(&mut self, path: &Path) {
+        self.sizes.remove(path);
+    }
+}
+
+/// Start watcher sending events of inbox to specified channel.
+fn start_watcher(
+    config: &WatchConfig,
+    sender: mpsc::Sender<notify::Result<Event>>,
+) -> Result<Box<dyn Watcher>> {
+    let mut watcher: Box<dyn Watcher> = if config.poll {
+        poll_watcher(config, sender)?
+    } else {
+        match RecommendedWatcher::new(sender.clone(), notify::Config::default()) {
+            Ok(watcher) => Box::new(watcher),
+            Err(err) => {
+                warn!("native notifications not available ({err}), polling instead");
+                poll_watcher(config, sender)?
+            }
+        }
+    };
+    watcher
+        .watch(&config.inbox, RecursiveMode::NonRecursive)
+        .map_err(|err| anyhow!("cannot watch {}: {err}", config.inbox.display()))?;
+
+    Ok(watcher)
+}
+
+/// Build watcher polling inbox at configured interval.
+fn poll_watcher(
+    config: &WatchConfig,
+    sender: mpsc::Sender<notify::Result<Event>>,
+) -> Result<Box<dyn Watcher>> {
+    let poll_config = notify::Config::default().with_poll_interval(config.interval);
+    Ok(Box::new(PollWatcher::new(sender, poll_config)?))
+}
+
+/// Check if specified path is a file matching profile naming pattern.
+fn is_candidate(path: &Path, profile: &Profile) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| profile.extract_version(name).is_some())
+        && path.is_file()
+}
+
+/// Files of inbox matching profile naming pattern, sorted by path.
+fn scan_inbox(inbox: &Path, profile: &Profile) -> Result<Vec<PathBuf>> {
+    let entries =
+        fs::read_dir(inbox).map_err(|err| anyhow!("cannot read {}: {err}", inbox.display()))?;
+    let mut paths = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if is_candidate(&path, profile) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    Ok(paths)
+}
+
+/// Watch inbox and convert each file matching profile naming pattern, present at startup or new,
+/// with configuration built by specified function.
+///
+/// Only returns on error, files are watched until the process is stopped.
+///
+/// # Errors
+///
+/// Will return `Err` if inbox cannot be read or watched.
+pub fn watch<F>(config: &WatchConfig, profile: &Profile, build: F) -> Result<()>
+where
+    F: Fn(&Path) -> Result<Config>,
+{
+    let (sender, receiver) = mpsc::channel();
+    let _watcher = start_watcher(config, sender)?;
+    info!(
+        "Watching {} for files matching `{}`",
+        config.inbox.display(),
+        profile.pattern
+    );
+
+    let mut pending = Pending::default();
+    // Events report absolute paths, existing files are found with the same ones
+    let inbox = fs::canonicalize(&config.inbox)
+        .map_err(|err| anyhow!("cannot read {}: {err}", config.inbox.display()))?;
+    for path in scan_inbox(&inbox, profile)? {
+        debug!("found {}", path.display());
+        pending.add(path);
+    }
+    let mut converted = HashSet::new();
+    let mut last_check = Instant::now();
+    loop {
+        match receiver.recv_timeout(config.interval) {
+            Ok(Ok(event)) => {
+                debug!("{:?} {:?}", event.kind, event.paths);
+                if event.kind.is_access() {
+                    // Files read by conversions, or given up files which are not modified
+                    continue;
+                }
+                for path in event.paths {
+                    if !path.exists() {
+                        // Moved or removed, a new file with the same name will be converted
+                        converted.remove(&path);
+                        continue;
+                    }
+                    if is_candidate(&path, profile) && !converted.contains(&path) {
+                        pending.add(path);
+                    }
+                }
+            }
+            Ok(Err(err)) => warn!("{err}"),
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(anyhow!("watcher of {} stopped", config.inbox.display()))
+            }
+        }
+
+        if last_check.elapsed() < config.interval {
+            continue;
+        }
+        last_check = Instant::now();
+
+        for path in pending.paths() {
+            let size = fs::metadata(&path).ok().map(|metadata| metadata.len());
+            if !pending.update(&path, size, last_check) {
+                continue;
+            }
+            let config_for_file = match build(&path) {
+                Ok(config_for_file) => config_for_file,
+                Err(err) => {
+                    error!("{}: {err}", path.display());
+                    pending.remove(&path);
+                    continue;
+                }
+            };
+            if config_for_file.output_file_path.exists() {
+                debug!(
+                    "{} skipped, {} already exists",
+                    path.display(),
+                    config_for_file.output_file_path.display()
+                );
+                pending.remove(&path);
+                converted.insert(path);
+                continue;
+            }
+            if let Err(err) = config_for_file.input_archive() {
+                if pending.expired(&path, last_check, config.timeout) {
+                    error!(
+                        "{}: still not a valid bundle after {}s, giving up until it is modified: {err}",
+                        path.display(),
+                        config.timeout.as_secs()
+                    );
+                    pending.remove(&path);
+                } else {
+                    // Not a complete Zip file yet
+                    debug!("{} not ready: {err}", path.display());
+                }
+                continue;
+            }
+
+            pending.remove(&path);
+            converted.insert(path.clone());
+            process(config, &config_for_file);
+        }
+    }
+}
+
+/// Convert file of specified configuration, and move it to processed directory if configured.
+fn process(config: &WatchConfig, file_config: &Config) {
+    let input = &file_config.input_file_path;
+    info!(
+        "Converting {} to {}",
+        input.display(),
+        file_config.output_file_path.display()
+    );
+    match convert(file_config) {
+        Ok(summary) => {
+            for warning in summary.warnings {
+                warn!("{warning}");
+            }
+            info!("Converted {}", input.display());
+        }
+        Err(err) => {
+            error!("{}: {err}", input.display());
+            return;
+        }
+    }
+
+    if let Some(processed_dir) = &config.processed_dir {
+        let Some(file_name) = input.file_name() else {
+            return;
+        };
+        let destination = processed_dir.join(file_name);
+        match fs::rename(input, &destination) {
+            Ok(()) => info!("Moved {} to {}", input.display(), destination.display()),
+            Err(err) => error!(
+                "cannot move {} to {}: {err}",
+                input.display(),
+                destination.display()
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn it_waits_for_stable_size() {
+        let mut pending = Pending::default();
+        let path = Path::new("MAME 0.264 EXTRAs.zip");
+        let now = Instant::now();
+        pending.add(path.to_path_buf());
+        assert!(!pending.update(path, Some(10), now));
+        assert!(!pending.update(path, Some(20), now));
+        assert!(pending.update(path, Some(20), now));
+    }
+
+    #[test]
+    fn it_drops_removed_files() {
+        let mut pending = Pending::default();
+        let path = Path::new("MAME 0.264 EXTRAs.zip");
+        pending.add(path.to_path_buf());
+        assert!(!pending.update(path, None, Instant::now()));
+        assert!(pending.paths().is_empty());
+    }
+
+    #[test]
+    fn it_keeps_size_when_added_again() {
+        let mut pending = Pending::default();
+        let path = Path::new("MAME 0.264 EXTRAs.zip");
+        let now = Instant::now();
+        pending.add(path.to_path_buf());
+        pending.update(path, Some(10), now);
+        pending.add(path.to_path_buf());
+        assert!(pending.update(path, Some(10), now));
+    }
+
+    #[test]
+    fn it_expires_files_with_stable_size() {
+        let mut pending = Pending::default();
+        let path = Path::new("MAME 0.264 EXTRAs.zip");
+        let timeout = Duration::from_secs(50);
+        let start = Instant::now();
+        pending.add(path.to_path_buf());
+        assert!(!pending.expired(path, start, timeout));
+        pending.update(path, Some(10), start);
+        pending.update(path, Some(10), start + Duration::from_secs(30));
+        assert!(!pending.expired(path, start + Duration::from_secs(30), timeout));
+        pending.update(path, Some(20), start + Duration::from_secs(90));
+        assert!(!pending.expired(path, start + Duration::from_secs(130), timeout));
+        assert!(pending.expired(path, start + Duration::from_secs(141), timeout));
+    }
+
+    #[test]
+    fn it_scans_existing_files() {
+        let dir = env::temp_dir().join("convert-mame-extras-romvault-watch");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("MAME 0.263 EXTRAs.zip")).unwrap();
+        for file in [
+            "MAME 0.265 EXTRAs.zip",
+            "MAME 0.264 EXTRAs.zip",
+            "notes.txt",
+        ] {
+            fs::write(dir.join(file), "").unwrap();
+        }
+
+        let paths = scan_inbox(&dir, &Profile::default()).unwrap();
+        assert_eq!(
+            vec![
+                dir.join("MAME 0.264 EXTRAs.zip"),
+                dir.join("MAME 0.265 EXTRAs.zip")
+            ],
+            paths
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,27 +1,82 @@
 //! Zip file handlers.
+//!
+//! The input Zip file is read in memory, or memory-mapped on request, and its central directory
+//! is read once. The resulting [`SharedArchive`] can be cloned cheaply, each clone reading entries
+//! independently, so that source dats are read in parallel without reading the file again.
 
 use anyhow::anyhow;
+use clap::ValueEnum;
+use memmap2::Mmap;
 use std::fs::{self};
-use std::io::{BufReader, ErrorKind};
+use std::io::{Cursor, ErrorKind, Read};
 use std::path::Path;
-
-use crate::files::FILES;
+use std::sync::Arc;
+use zip::ZipArchive;
 
 /// Custom result with any context error.
 type Result<T> = anyhow::Result<T>;
 
+/// Access to the content of input file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum InputAccess {
+    /// Read file in memory once.
+    #[default]
+    Read,
+    /// Memory-map file, which kills the process (SIGBUS) if the file is truncated or becomes unreadable while mapped.
+    Mmap,
+}
+
+/// Content of input file, shared between readers.
+#[derive(Clone, Debug)]
+pub enum InputContent {
+    /// File read in memory.
+    Read(Arc<[u8]>),
+    /// Memory-mapped file.
+    Mapped(Arc<Mmap>),
+}
+
+impl AsRef<[u8]> for InputContent {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            Self::Read(content) => content,
+            Self::Mapped(mmap) => mmap,
+        }
+    }
+}
+
+/// Zip archive of an input file content, with its central directory read once.
+///
+/// Clones share the central directory and the content, and have their own read position.
+pub type SharedArchive = ZipArchive<Cursor<InputContent>>;
+
 /// Check if input file is accessible, is a valid Zip, and contains the expected entries.
-/// Expected entries are :
+/// Expected entries depend on selected sections, and are all of :
 /// - all_non-zipped_content.dat
 /// - artwork.dat
 /// - samples.dat
 ///
+/// when all sections are converted with built-in mapping (see [`crate::mapping::Mapping::required_entries`]).
+///
 /// # Errors
 /// - File does not exists
 /// - File not accessible (permission denied)
 /// - File is not a valid Zip file
 /// - Zip file doesn't contain expected entries
-pub fn check_input_file(input_file_path: &Path) -> Result<()> {
+pub fn check_input_file(input_file_path: &Path, expected_entries: &[&str]) -> Result<()> {
+    open_input_file(input_file_path, expected_entries, InputAccess::Read).map(|_| ())
+}
+
+/// Open input file as a shared archive, after checking it as [`check_input_file`] does.
+/// The file is read in memory, or memory-mapped, according to specified access.
+///
+/// # Errors
+///
+/// Will return `Err` for the same reasons as [`check_input_file`], or if file cannot be read or memory-mapped.
+pub fn open_input_file(
+    input_file_path: &Path,
+    expected_entries: &[&str],
+    access: InputAccess,
+) -> Result<SharedArchive> {
     // Check if input file exists and can be accessed
     let file = match fs::OpenOptions::new()
         .read(true)
@@ -46,22 +101,53 @@ pub fn check_input_file(input_file_path: &Path) -> Result<()> {
         },
     };
 
-    let reader = BufReader::new(file);
+    let content = if access == InputAccess::Mmap {
+        // SAFETY: the mapping is read-only, and input files are not expected to be modified while
+        // converted (watch mode only converts files once their size is stable). However, if the
+        // file is truncated or becomes unreadable while mapped (e.g. a network share going away),
+        // reading the mapping raises SIGBUS and kills the process instead of returning an error.
+        // This is why mapping is only used on request, input files are read in memory otherwise.
+        let mmap = unsafe { Mmap::map(&file) }
+            .map_err(|err| anyhow!("cannot map `{}`: {err}", input_file_path.display()))?;
+        InputContent::Mapped(Arc::new(mmap))
+    } else {
+        let mut content = Vec::new();
+        (&file)
+            .read_to_end(&mut content)
+            .map_err(|err| anyhow!("cannot read `{}`: {err}", input_file_path.display()))?;
+        InputContent::Read(content.into())
+    };
 
     // Check if input file is a valid zip
-    let Ok(archive) = zip::ZipArchive::new(reader) else {
+    let Ok(archive) = ZipArchive::new(Cursor::new(content)) else {
         return Err(anyhow!(
             "the file `{}` is not a valid Zip file",
             input_file_path.display().to_string()
         ));
     };
 
+    check_entries(&archive, expected_entries)?;
+
+    Ok(archive)
+}
+
+/// Check that specified archive contains all expected entries.
+///
+/// # Errors
+///
+/// Will return `Err` if an expected entry is missing.
+pub fn check_entries(archive: &SharedArchive, expected_entries: &[&str]) -> Result<()> {
     // Check if input ZIP file contains all expected files
     let entries: Vec<&str> = archive.file_names().collect();
-    if !FILES.iter().all(|dat_file| entries.contains(dat_file)) {
+    if !expected_entries
+        .iter()
+        .all(|dat_file| entries.contains(dat_file))
+    {
         return Err(anyhow!(
-            "input Zip file must contains 3 files: {}",
-            FILES.join(", "),
+            "input Zip file must contains {} file{}: {}",
+            expected_entries.len(),
+            if expected_entries.len() > 1 { "s" } else { "" },
+            expected_entries.join(", "),
         ));
     }
 
@@ -74,24 +160,24 @@ mod tests {
 
     use zip::write::SimpleFileOptions;
 
-    use crate::files::{ALL_NON_ZIPPED_CONTENT, ARTWORK, SAMPLES};
+    use crate::files::{ALL_NON_ZIPPED_CONTENT, ARTWORK, FILES, SAMPLES};
 
     use super::*;
 
     #[test]
     fn it_should_handle_unexisting_file() {
         let file = Path::new("foo.txt");
-        let result = check_input_file(file);
+        let result = check_input_file(file, &FILES);
         assert!(result.is_err());
         if let Err(err) = result {
             assert_eq!("the file `foo.txt` does not exist", err.to_string());
-        };
+        }
     }
 
     #[test]
     fn it_should_handle_permission_denied_error() {
         let file = Path::new("/root/foo.txt");
-        let result = check_input_file(file);
+        let result = check_input_file(file, &FILES);
         assert!(result.is_err());
         if let Err(err) = result {
             assert_eq!(
@@ -112,14 +198,14 @@ mod tests {
             .write(true)
             .open(&fname);
         assert!(file_result.is_ok());
-        let result = check_input_file(&fname);
+        let result = check_input_file(&fname, &FILES);
         assert!(result.is_err());
         if let Err(err) = result {
             assert_eq!(
                 format!("the file `{}` is not a valid Zip file", fname.display()),
                 err.to_string()
             );
-        };
+        }
         let remove_result = fs::remove_file(fname);
         assert!(remove_result.is_ok());
     }
@@ -172,14 +258,46 @@ mod tests {
         let zip_finish_result = zip.finish();
         assert!(zip_finish_result.is_ok());
 
-        let result = check_input_file(&fname);
+        let result = check_input_file(&fname, &FILES);
         assert!(result.is_err());
         if let Err(err) = result {
             assert_eq!(
                 format!("input Zip file must contains 3 files: all_non-zipped_content.dat, artwork.dat, samples.dat"),
                 err.to_string()
             );
-        };
+        }
+
+        let remove_result = fs::remove_file(fname);
+        assert!(remove_result.is_ok());
+    }
+
+    #[test]
+    fn it_should_only_require_expected_entries() {
+        let temp_dir = env::temp_dir();
+        let fname = temp_dir.join("it_should_only_require_expected_entries.zip");
+        let file_result = fs::OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open(&fname);
+        assert!(file_result.is_ok());
+
+        let mut zip = zip::ZipWriter::new(file_result.unwrap());
+        let options = SimpleFileOptions::default();
+        assert!(zip.start_file(ARTWORK, options).is_ok());
+        assert!(zip.finish().is_ok());
+
+        assert!(check_input_file(&fname, &[ARTWORK]).is_ok());
+        let archive = open_input_file(&fname, &[ARTWORK], InputAccess::Mmap);
+        assert!(archive.is_ok_and(|archive| archive.file_names().eq([ARTWORK])));
+        let result = check_input_file(&fname, &[SAMPLES]);
+        assert!(result.is_err());
+        if let Err(err) = result {
+            assert_eq!(
+                "input Zip file must contains 1 file: samples.dat",
+                err.to_string()
+            );
+        }
 
         let remove_result = fs::remove_file(fname);
         assert!(remove_result.is_ok());
@@ -5,7 +5,7 @@ use std::io::{BufReader, Read};
 
 type Result<T> = anyhow::Result<T>;
 
-pub fn compare_digests(output_file: &String, expected_file: &String) -> Result<bool> {
+pub fn compare_digests(output_file: &str, expected_file: &str) -> Result<bool> {
     let output_file_path = File::open(output_file)?;
     let reader = BufReader::new(output_file_path);
     let output_file_digest = sha256_digest(reader)?;
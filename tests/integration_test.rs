@@ -45,7 +45,7 @@ fn it_runs_with_1_argument() -> Result<()> {
     let output_file = String::from("MAME 0.264 EXTRAs.dat");
     let expected_file = String::from("tests/assets/expected/MAME 0.264 EXTRAs.dat");
     let mut cmd = Command::cargo_bin("convert-mame-extras-romvault")?;
-    let status = cmd.arg(input_file).status().expect("Failure");
+    let status = cmd.arg(input_file).arg("--date=none").status().expect("Failure");
 
     assert!(status.success());
 
@@ -59,7 +59,7 @@ fn it_runs_with_1_argument() -> Result<()> {
     Ok(())
 }
 
-fn it_runs_with_2_arguments(input_file: String, expected_file: String, output_file_name: &str) -> Result<()> {
+fn it_runs_with_2_arguments(input_file: String, expected_file: &str, output_file_name: &str) -> Result<()> {
     let temp_dir_path = env::temp_dir();
 
     // Create arguments
@@ -70,13 +70,14 @@ fn it_runs_with_2_arguments(input_file: String, expected_file: String, output_fi
     let status = cmd
         .arg(input_file)
         .arg(output_file.clone())
+        .arg("--date=none")
         .status()
         .expect("Failure");
 
     assert!(status.success());
 
     // Compare files digests
-    assert!(compare_digests(&output_file, &expected_file).unwrap());
+    assert!(compare_digests(&output_file, expected_file).unwrap());
 
     assert!(fs::remove_file(output_file_path).is_ok());
 
@@ -87,47 +88,145 @@ fn it_runs_with_2_arguments(input_file: String, expected_file: String, output_fi
 fn it_runs_with_2_arguments_262() -> Result<()> {
     let input_file = String::from("tests/assets/MAME 0.262 EXTRAs.zip");
     let expected_file = String::from("tests/assets/expected/MAME 0.262 EXTRAs.dat");
-    it_runs_with_2_arguments(input_file, expected_file, "extras262.dat")
+    it_runs_with_2_arguments(input_file, &expected_file, "extras262.dat")
 }
 
 #[test]
 fn it_runs_with_2_arguments_264() -> Result<()> {
     let input_file = String::from("tests/assets/MAME 0.264 EXTRAs.zip");
     let expected_file = String::from("tests/assets/expected/MAME 0.264 EXTRAs.dat");
-    it_runs_with_2_arguments(input_file, expected_file, "extras264.dat")
+    it_runs_with_2_arguments(input_file, &expected_file, "extras264.dat")
 }
 
 #[test]
 fn it_runs_with_2_arguments_266() -> Result<()> {
     let input_file = String::from("tests/assets/MAME 0.266 EXTRAs.zip");
     let expected_file = String::from("tests/assets/expected/MAME 0.266 EXTRAs.dat");
-    it_runs_with_2_arguments(input_file, expected_file, "extras266.dat")
+    it_runs_with_2_arguments(input_file, &expected_file, "extras266.dat")
 }
 
 #[test]
 fn it_runs_with_2_arguments_269() -> Result<()> {
     let input_file = String::from("tests/assets/MAME 0.269 EXTRAs.zip");
     let expected_file = String::from("tests/assets/expected/MAME 0.269 EXTRAs.dat");
-    it_runs_with_2_arguments(input_file, expected_file, "extras269.dat")
+    it_runs_with_2_arguments(input_file, &expected_file, "extras269.dat")
 }
 
 #[test]
 fn it_runs_with_2_arguments_270() -> Result<()> {
     let input_file = String::from("tests/assets/MAME 0.270 EXTRAs.zip");
     let expected_file = String::from("tests/assets/expected/MAME 0.270 EXTRAs.dat");
-    it_runs_with_2_arguments(input_file, expected_file, "extras270.dat")
+    it_runs_with_2_arguments(input_file, &expected_file, "extras270.dat")
 }
 
 #[test]
 fn it_runs_with_2_arguments_272() -> Result<()> {
     let input_file = String::from("tests/assets/MAME 0.272 EXTRAs.zip");
     let expected_file = String::from("tests/assets/expected/MAME 0.272 EXTRAs.dat");
-    it_runs_with_2_arguments(input_file, expected_file, "extras272.dat")
+    it_runs_with_2_arguments(input_file, &expected_file, "extras272.dat")
 }
 
 #[test]
 fn it_runs_with_2_arguments_276() -> Result<()> {
     let input_file = String::from("tests/assets/MAME 0.276 EXTRAs.zip");
     let expected_file = String::from("tests/assets/expected/MAME 0.276 EXTRAs.dat");
-    it_runs_with_2_arguments(input_file, expected_file, "extras276.dat")
+    it_runs_with_2_arguments(input_file, &expected_file, "extras276.dat")
+}
+
+#[test]
+fn it_runs_convert_command() -> Result<()> {
+    let output_file_path = env::temp_dir().join("extras264-convert.dat");
+    let output_file = output_file_path.to_string_lossy().to_string();
+
+    let mut cmd = Command::cargo_bin("convert-mame-extras-romvault")?;
+    let status = cmd
+        .arg("convert")
+        .arg("tests/assets/MAME 0.264 EXTRAs.zip")
+        .arg(output_file.clone())
+        .arg("--date=none")
+        .status()
+        .expect("Failure");
+
+    assert!(status.success());
+    let expected_file = String::from("tests/assets/expected/MAME 0.264 EXTRAs.dat");
+    assert!(compare_digests(&output_file, &expected_file).unwrap());
+    assert!(fs::remove_file(output_file_path).is_ok());
+
+    Ok(())
+}
+
+#[test]
+fn it_verifies_expected_output() -> Result<()> {
+    let mut cmd = Command::cargo_bin("convert-mame-extras-romvault")?;
+    let status = cmd
+        .arg("verify")
+        .arg("tests/assets/MAME 0.264 EXTRAs.zip")
+        .arg("tests/assets/expected/MAME 0.264 EXTRAs.dat")
+        .arg("--date=none")
+        .status()
+        .expect("Failure");
+
+    assert!(status.success());
+
+    Ok(())
+}
+
+#[test]
+fn it_verifies_compressed_output() -> Result<()> {
+    let output_file_path = env::temp_dir().join("extras264-verify.dat.gz");
+    let output_file = output_file_path.to_string_lossy().to_string();
+    let _ = fs::remove_file(&output_file_path);
+
+    let mut cmd = Command::cargo_bin("convert-mame-extras-romvault")?;
+    let status = cmd
+        .arg("convert")
+        .arg("tests/assets/MAME 0.264 EXTRAs.zip")
+        .arg(output_file.clone())
+        .status()
+        .expect("Failure");
+    assert!(status.success());
+
+    let mut cmd = Command::cargo_bin("convert-mame-extras-romvault")?;
+    let status = cmd
+        .arg("verify")
+        .arg("tests/assets/MAME 0.264 EXTRAs.zip")
+        .arg(output_file.clone())
+        .status()
+        .expect("Failure");
+    assert!(status.success());
+
+    let mut cmd = Command::cargo_bin("convert-mame-extras-romvault")?;
+    let output = cmd
+        .arg("diff")
+        .arg("tests/assets/expected/MAME 0.264 EXTRAs.dat")
+        .arg(output_file)
+        .output()
+        .expect("Failure");
+    assert!(output.status.success());
+    assert_eq!(
+        "0 added, 0 removed, 0 changed\n",
+        String::from_utf8(output.stdout).unwrap()
+    );
+    assert!(fs::remove_file(output_file_path).is_ok());
+
+    Ok(())
+}
+
+#[test]
+fn it_diffs_dats() -> Result<()> {
+    let mut cmd = Command::cargo_bin("convert-mame-extras-romvault")?;
+    let output = cmd
+        .arg("diff")
+        .arg("tests/assets/expected/MAME 0.264 EXTRAs.dat")
+        .arg("tests/assets/expected/MAME 0.264 EXTRAs.dat")
+        .output()
+        .expect("Failure");
+
+    assert!(output.status.success());
+    assert_eq!(
+        "0 added, 0 removed, 0 changed\n",
+        String::from_utf8(output.stdout).unwrap()
+    );
+
+    Ok(())
 }